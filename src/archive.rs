@@ -0,0 +1,148 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{clock, ScraperTask};
+
+/// Every page fetched, kept so improved parsers can be run over old pages and
+/// parsing bugs reproduced.
+///
+/// Bodies are gzipped under `objects/` named by the SHA-256 of their content,
+/// so a page that didn't change is stored once. `index.jsonl` gets a line per
+/// fetch.
+pub struct Archive {
+    dir: PathBuf,
+    index: Mutex<File>,
+}
+
+/// One fetch of a page.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ArchiveEntry {
+    /// Address fetched, including the query the scraper added.
+    pub url: String,
+    pub task: ScraperTask,
+    pub fetched_at: DateTime<Utc>,
+    /// HTTP status of the response.
+    pub status: u16,
+    /// SHA-256 of the body, naming its object.
+    pub sha256: String,
+    /// Size of the body before compression.
+    pub bytes: usize,
+}
+
+impl Archive {
+    /// Opens the archive in `dir`, creating it when missing.
+    pub fn open(dir: &Path) -> std::io::Result<Archive> {
+        std::fs::create_dir_all(dir.join("objects"))?;
+        let index = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join("index.jsonl"))?;
+        Ok(Archive {
+            dir: dir.to_owned(),
+            index: Mutex::new(index),
+        })
+    }
+
+    /// Stores the `body` of the response to `url`, fetched for `task`.
+    pub fn store(
+        &self,
+        task: &ScraperTask,
+        url: &str,
+        status: u16,
+        body: &str,
+    ) -> std::io::Result<ArchiveEntry> {
+        let sha256 = format!("{:x}", Sha256::digest(body.as_bytes()));
+        let path = self.object_path(&sha256);
+        if !path.exists() {
+            std::fs::create_dir_all(path.parent().expect("objects directory"))?;
+            // Written aside and renamed, so a crash never leaves a torn object.
+            let partial = path.with_extension("partial");
+            let mut encoder = GzEncoder::new(File::create(&partial)?, Compression::default());
+            encoder.write_all(body.as_bytes())?;
+            encoder.finish()?;
+            std::fs::rename(&partial, &path)?;
+        }
+        let entry = ArchiveEntry {
+            url: url.to_owned(),
+            task: task.clone(),
+            fetched_at: clock::now(),
+            status,
+            sha256,
+            bytes: body.len(),
+        };
+        let line = serde_json::to_string(&entry).expect("serializable entry");
+        let mut index = self.index.lock().expect("archive index lock");
+        writeln!(index, "{}", line)?;
+        index.flush()?;
+        Ok(entry)
+    }
+
+    /// The fetches recorded so far, oldest first.
+    pub fn entries(&self) -> std::io::Result<Vec<ArchiveEntry>> {
+        let file = File::open(self.dir.join("index.jsonl"))?;
+        let mut entries = vec![];
+        for line in BufReader::new(file).lines() {
+            // A crash can leave a torn last line behind, skip it.
+            if let Ok(entry) = serde_json::from_str(&line?) {
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Body of the page fetched in `entry`.
+    pub fn read(&self, entry: &ArchiveEntry) -> std::io::Result<String> {
+        let mut body = String::new();
+        GzDecoder::new(File::open(self.object_path(&entry.sha256))?).read_to_string(&mut body)?;
+        Ok(body)
+    }
+
+    fn object_path(&self, sha256: &str) -> PathBuf {
+        self.dir
+            .join("objects")
+            .join(&sha256[..2])
+            .join(format!("{}.html.gz", &sha256[2..]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::archive::Archive;
+    use crate::livescores::GamesParser;
+    use crate::ScripingFunction;
+
+    #[test]
+    fn test_archive_stores_each_body_once() {
+        let dir = std::env::temp_dir().join(format!("archive-{}", std::process::id()));
+        let archive = Archive::open(&dir).unwrap();
+        let task = GamesParser::new_task("https://www.livescores.com", "/football/england/");
+        let url = "https://www.livescores.com/football/england/?tz=0";
+        let first = archive.store(&task, url, 200, "<html>one</html>").unwrap();
+        let second = archive.store(&task, url, 200, "<html>one</html>").unwrap();
+        let third = archive.store(&task, url, 503, "<html>busy</html>").unwrap();
+        assert_eq!(first.sha256, second.sha256);
+        assert_ne!(first.sha256, third.sha256);
+        let objects: usize = std::fs::read_dir(dir.join("objects"))
+            .unwrap()
+            .map(|prefix| std::fs::read_dir(prefix.unwrap().path()).unwrap().count())
+            .sum();
+        assert_eq!(objects, 2);
+
+        let reopened = Archive::open(&dir).unwrap();
+        let entries = reopened.entries().unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0], first);
+        assert_eq!(entries[2].status, 503);
+        assert_eq!(reopened.read(&entries[2]).unwrap(), "<html>busy</html>");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
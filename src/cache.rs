@@ -0,0 +1,163 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::fetch::{FetchError, Fetcher, Request, Response};
+
+/// Status the server answers a conditional request with when the page is
+/// unchanged.
+pub const NOT_MODIFIED: u16 = 304;
+
+/// Keeps the pages served with an `ETag` or `Last-Modified` header and asks
+/// for them again only if they changed. A `304 Not Modified` is answered with
+/// the cached body, so the page is parsed as before without downloading it.
+pub struct CachingFetcher<F> {
+    inner: F,
+    dir: PathBuf,
+}
+
+/// A cached page, gzipped in a file named after the SHA-256 of its URL.
+#[derive(Serialize, Deserialize)]
+struct CachedPage {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    body: String,
+}
+
+impl<F: Fetcher> CachingFetcher<F> {
+    pub fn open(inner: F, dir: &Path) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        Ok(CachingFetcher {
+            inner,
+            dir: dir.to_owned(),
+        })
+    }
+
+    fn path(&self, url: &str) -> PathBuf {
+        self.dir
+            .join(format!("{:x}.json.gz", Sha256::digest(url.as_bytes())))
+    }
+
+    fn load(&self, url: &str) -> Option<CachedPage> {
+        let mut content = String::new();
+        GzDecoder::new(File::open(self.path(url)).ok()?)
+            .read_to_string(&mut content)
+            .ok()?;
+        serde_json::from_str::<CachedPage>(&content)
+            .ok()
+            .filter(|page| page.url == url)
+    }
+
+    fn save(&self, page: &CachedPage) -> std::io::Result<()> {
+        let path = self.path(&page.url);
+        // Written aside and renamed, so a crash never leaves a torn page.
+        let partial = path.with_extension("partial");
+        let mut encoder = GzEncoder::new(File::create(&partial)?, Compression::default());
+        encoder.write_all(&serde_json::to_vec(page)?)?;
+        encoder.finish()?;
+        std::fs::rename(&partial, &path)
+    }
+}
+
+impl<F: Fetcher> Fetcher for CachingFetcher<F> {
+    fn fetch<'a>(&'a self, request: &'a Request) -> BoxFuture<'a, Result<Response, FetchError>> {
+        Box::pin(async move {
+            let cached = self.load(&request.url);
+            let conditional = match &cached {
+                Some(page) => Request {
+                    url: request.url.clone(),
+                    if_none_match: page.etag.clone(),
+                    if_modified_since: page.last_modified.clone(),
+                },
+                None => request.clone(),
+            };
+            let mut response = self.inner.fetch(&conditional).await?;
+            match cached {
+                Some(page) if response.status == NOT_MODIFIED => {
+                    response.body = page.body;
+                    response.etag = response.etag.or(page.etag);
+                    response.last_modified = response.last_modified.or(page.last_modified);
+                }
+                _ if response.status == 200
+                    && (response.etag.is_some() || response.last_modified.is_some()) =>
+                {
+                    let page = CachedPage {
+                        url: request.url.clone(),
+                        etag: response.etag.clone(),
+                        last_modified: response.last_modified.clone(),
+                        body: response.body.clone(),
+                    };
+                    if let Err(e) = self.save(&page) {
+                        println!("Cache Error {}: {}", request.url, e);
+                    }
+                }
+                _ => {}
+            }
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use futures::future::BoxFuture;
+
+    use crate::cache::{CachingFetcher, NOT_MODIFIED};
+    use crate::fetch::{FetchError, Fetcher, Request, Response};
+
+    /// Serves a page tagged "v1" and honours `If-None-Match`.
+    #[derive(Default)]
+    struct Origin {
+        requests: Mutex<Vec<Request>>,
+    }
+
+    impl Fetcher for Origin {
+        fn fetch<'a>(
+            &'a self,
+            request: &'a Request,
+        ) -> BoxFuture<'a, Result<Response, FetchError>> {
+            self.requests.lock().unwrap().push(request.clone());
+            let response = match request.if_none_match.as_deref() {
+                Some("\"v1\"") => Response::new(NOT_MODIFIED, ""),
+                _ => Response {
+                    etag: Some("\"v1\"".to_owned()),
+                    ..Response::new(200, "<html>games</html>")
+                },
+            };
+            Box::pin(async move { Ok(response) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unchanged_page_is_served_from_cache() {
+        let dir = std::env::temp_dir().join(format!("cache-{}", std::process::id()));
+        let cache = CachingFetcher::open(Origin::default(), &dir).unwrap();
+        let request = Request::get("https://www.livescores.com/football/england/");
+
+        let first = cache.fetch(&request).await.unwrap();
+        assert_eq!(
+            (first.status, first.body.as_str()),
+            (200, "<html>games</html>")
+        );
+        let second = cache.fetch(&request).await.unwrap();
+        assert_eq!(
+            (second.status, second.body.as_str()),
+            (NOT_MODIFIED, "<html>games</html>")
+        );
+
+        let requests = cache.inner.requests.lock().unwrap();
+        assert_eq!(requests[0].if_none_match, None);
+        assert_eq!(requests[1].if_none_match.as_deref(), Some("\"v1\""));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,97 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Scrapes football leagues and games from livescores.com"
+)]
+pub struct Cli {
+    /// Crawl settings, scraper.toml is used when it exists.
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+    /// Append every response to this cassette file.
+    #[arg(long, global = true, conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+    /// Serve responses from a recorded cassette instead of the network.
+    #[arg(long, global = true)]
+    pub replay: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Crawls the site from the start URL, following every league and game.
+    Crawl {
+        /// Start here instead of at the config's start_urls.
+        #[arg(long)]
+        start_url: Option<String>,
+        /// Maximum number of pages fetched at the same time.
+        #[arg(long)]
+        concurrency: Option<usize>,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Fetches and parses a single page.
+    Fetch {
+        url: String,
+        /// Parser for the page: main, league_group, games or match_detail.
+        #[arg(long)]
+        parser: String,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Parses a page saved to disk, without touching the network.
+    ParseFile {
+        path: PathBuf,
+        #[arg(long)]
+        parser: String,
+        /// Address the page was saved from, used to resolve links and the season.
+        #[arg(long, default_value = "https://www.livescores.com/")]
+        url: String,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Parses the archived pages again with the current parsers and shows how
+    /// the games differ from the stored ones.
+    Reparse {
+        /// Only pages whose URL contains this.
+        url: Option<String>,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Lists the archived fetches, oldest first.
+    Archive {
+        /// Only fetches whose URL contains this.
+        url: Option<String>,
+        /// Print the page of the latest matching fetch instead.
+        #[arg(long)]
+        body: bool,
+    },
+    /// Writes the games stored in the database to a file.
+    Export {
+        #[arg(long, value_parser = ["jsonl", "csv", "parquet"])]
+        sink: String,
+        #[arg(long)]
+        output: PathBuf,
+        /// Database to export, defaults to the config's output.database.
+        #[arg(long)]
+        database: Option<PathBuf>,
+    },
+}
+
+#[derive(Args)]
+pub struct OutputArgs {
+    /// Also write the extracted data to a file.
+    #[arg(long, value_parser = ["jsonl", "csv", "parquet"], requires = "output")]
+    pub sink: Option<String>,
+    /// File, or directory for parquet, the sink writes to.
+    #[arg(long, requires = "sink")]
+    pub output: Option<PathBuf>,
+    /// SQLite database the games are stored in. `crawl` defaults to the
+    /// config's output.database, the other commands only store when it is given.
+    #[arg(long)]
+    pub database: Option<PathBuf>,
+}
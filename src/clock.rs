@@ -0,0 +1,20 @@
+use std::cell::Cell;
+
+use chrono::{DateTime, Utc};
+
+thread_local! {
+    static FIXED: Cell<Option<DateTime<Utc>>> = const { Cell::new(None) };
+}
+
+/// Current time, or the time fixed by `with_time` on this thread.
+pub fn now() -> DateTime<Utc> {
+    FIXED.with(|fixed| fixed.get()).unwrap_or_else(Utc::now)
+}
+
+/// Runs `f` with `now()` returning `time`.
+pub fn with_time<T>(time: DateTime<Utc>, f: impl FnOnce() -> T) -> T {
+    let previous = FIXED.with(|fixed| fixed.replace(Some(time)));
+    let result = f();
+    FIXED.with(|fixed| fixed.set(previous));
+    result
+}
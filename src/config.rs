@@ -0,0 +1,390 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::FixedOffset;
+use regex::Regex;
+use reqwest::Url;
+use serde::Deserialize;
+
+use crate::drift::HealthChecks;
+use crate::livescores::NAVIGATION_LINKS;
+use crate::APP_USER_AGENT;
+
+/// Settings of a crawl, read from a TOML file. Every key is optional and
+/// falls back to the value in `Config::default`.
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub start_urls: Vec<String>,
+    pub user_agent: String,
+    /// Time a request may take in all, from connecting to the end of the body.
+    pub timeout_ms: u64,
+    pub connect_timeout_ms: u64,
+    /// Offset from UTC, like "+03:00", the site renders kickoff times in.
+    /// The site only takes whole hours.
+    pub timezone: String,
+    /// Selector profiles to parse the pages with instead of the built-in ones.
+    pub profiles: Option<PathBuf>,
+    /// Countries in the site menu to crawl, matched against their titles.
+    pub countries: Patterns,
+    /// Leagues of a country to crawl, matched against their titles.
+    pub leagues: Patterns,
+    pub rate_limit: RateLimit,
+    pub output: OutputConfig,
+    /// Health checks that flag pages the selectors may no longer fit.
+    pub drift: HealthChecks,
+    pub games: GamesConfig,
+    pub archive: ArchiveConfig,
+    pub cache: CacheConfig,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Patterns {
+    /// Regular expressions of which at least one must match, empty to allow
+    /// everything.
+    pub include: Vec<String>,
+    /// Regular expressions of which none may match.
+    pub exclude: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimit {
+    pub concurrency: usize,
+    /// Minimum time between two requests to the same host.
+    pub delay_ms: u64,
+    /// `delay_ms` for specific hosts.
+    pub hosts: HashMap<String, u64>,
+    /// Requests a host may get at once before the delay applies.
+    pub burst: u32,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    pub database: PathBuf,
+    pub sink: Option<String>,
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct GamesConfig {
+    /// Share of the game rows of a page that may fail to parse before the
+    /// whole page is reported as failed.
+    pub max_error_ratio: f64,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArchiveConfig {
+    /// Keep a compressed copy of every fetched page.
+    pub enabled: bool,
+    pub dir: PathBuf,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// Keep pages served with an ETag or Last-Modified header and fetch them
+    /// again only when they changed.
+    pub enabled: bool,
+    pub dir: PathBuf,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, std::io::Error),
+    Syntax(PathBuf, toml::de::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, e) => write!(f, "Can't read {}: {}", path.display(), e),
+            ConfigError::Syntax(path, e) => write!(f, "Invalid config {}: {}", path.display(), e),
+            ConfigError::Invalid(message) => write!(f, "Invalid config: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            start_urls: vec!["https://www.livescores.com".to_owned()],
+            user_agent: APP_USER_AGENT.to_owned(),
+            timeout_ms: 30_000,
+            connect_timeout_ms: 10_000,
+            timezone: "+00:00".to_owned(),
+            profiles: None,
+            countries: Patterns {
+                include: vec![],
+                exclude: NAVIGATION_LINKS
+                    .iter()
+                    .map(|title| format!("^{}$", regex::escape(title)))
+                    .collect(),
+            },
+            leagues: Patterns::default(),
+            rate_limit: RateLimit::default(),
+            output: OutputConfig::default(),
+            drift: HealthChecks::default(),
+            games: GamesConfig::default(),
+            archive: ArchiveConfig::default(),
+            cache: CacheConfig::default(),
+        }
+    }
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        RateLimit {
+            concurrency: 4,
+            delay_ms: 1000,
+            hosts: HashMap::new(),
+            burst: 1,
+        }
+    }
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        OutputConfig {
+            database: PathBuf::from("livescores.sqlite"),
+            sink: None,
+            path: None,
+        }
+    }
+}
+
+impl Default for GamesConfig {
+    fn default() -> Self {
+        GamesConfig {
+            max_error_ratio: 0.2,
+        }
+    }
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        ArchiveConfig {
+            enabled: true,
+            dir: PathBuf::from("archive"),
+        }
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            enabled: true,
+            dir: PathBuf::from("cache"),
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let content =
+            std::fs::read_to_string(path).map_err(|e| ConfigError::Read(path.to_owned(), e))?;
+        let config: Config =
+            toml::from_str(&content).map_err(|e| ConfigError::Syntax(path.to_owned(), e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.start_urls.is_empty() {
+            return Err(ConfigError::Invalid("start_urls is empty".to_owned()));
+        }
+        for url in &self.start_urls {
+            match Url::parse(url) {
+                Ok(parsed) if parsed.scheme() == "http" || parsed.scheme() == "https" => {}
+                _ => {
+                    return Err(ConfigError::Invalid(format!(
+                        "start_urls: {} is not an http(s) address",
+                        url
+                    )))
+                }
+            }
+        }
+        if self.user_agent.trim().is_empty() {
+            return Err(ConfigError::Invalid("user_agent is empty".to_owned()));
+        }
+        if self.timeout_ms == 0 || self.connect_timeout_ms == 0 {
+            return Err(ConfigError::Invalid(
+                "timeout_ms and connect_timeout_ms must be at least 1".to_owned(),
+            ));
+        }
+        self.timezone()?;
+        self.countries.compile("countries")?;
+        self.leagues.compile("leagues")?;
+        if self.rate_limit.concurrency == 0 {
+            return Err(ConfigError::Invalid(
+                "rate_limit.concurrency must be at least 1".to_owned(),
+            ));
+        }
+        if self.rate_limit.burst == 0 {
+            return Err(ConfigError::Invalid(
+                "rate_limit.burst must be at least 1".to_owned(),
+            ));
+        }
+        if let Some(sink) = &self.output.sink {
+            if !["jsonl", "csv", "parquet"].contains(&sink.as_str()) {
+                return Err(ConfigError::Invalid(format!(
+                    "output.sink: unknown sink {}, expected jsonl, csv or parquet",
+                    sink
+                )));
+            }
+            if self.output.path.is_none() {
+                return Err(ConfigError::Invalid(
+                    "output.path is required with output.sink".to_owned(),
+                ));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.drift.min_parsed_ratio) {
+            return Err(ConfigError::Invalid(
+                "drift.min_parsed_ratio must be between 0 and 1".to_owned(),
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.games.max_error_ratio) {
+            return Err(ConfigError::Invalid(
+                "games.max_error_ratio must be between 0 and 1".to_owned(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn timezone(&self) -> Result<FixedOffset, ConfigError> {
+        let invalid = || {
+            ConfigError::Invalid(format!(
+                "timezone: {} is not a whole hour offset between -12:00 and +14:00",
+                self.timezone
+            ))
+        };
+        let timezone: FixedOffset = self.timezone.parse().map_err(|_| invalid())?;
+        let seconds = timezone.local_minus_utc();
+        if seconds % 3600 != 0 || !(-12 * 3600..=14 * 3600).contains(&seconds) {
+            return Err(invalid());
+        }
+        Ok(timezone)
+    }
+}
+
+impl Patterns {
+    /// Compiles the patterns; `section` names them in errors.
+    pub fn compile(&self, section: &str) -> Result<NameFilter, ConfigError> {
+        let compile = |key: &str, patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| {
+                    Regex::new(pattern)
+                        .map_err(|e| ConfigError::Invalid(format!("{}.{}: {}", section, key, e)))
+                })
+                .collect::<Result<Vec<Regex>, ConfigError>>()
+        };
+        Ok(NameFilter {
+            include: compile("include", &self.include)?,
+            exclude: compile("exclude", &self.exclude)?,
+        })
+    }
+}
+
+impl RateLimit {
+    pub fn delay(&self) -> Duration {
+        Duration::from_millis(self.delay_ms)
+    }
+
+    pub fn host_delays(&self) -> HashMap<String, Duration> {
+        self.hosts
+            .iter()
+            .map(|(host, ms)| (host.clone(), Duration::from_millis(*ms)))
+            .collect()
+    }
+}
+
+/// Compiled `Patterns`, the default allows every name.
+#[derive(Debug, Default, Clone)]
+pub struct NameFilter {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+impl NameFilter {
+    pub fn allows(&self, name: &str) -> bool {
+        let name = name.trim();
+        (self.include.is_empty() || self.include.iter().any(|re| re.is_match(name)))
+            && !self.exclude.iter().any(|re| re.is_match(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::config::{Config, Patterns};
+
+    #[test]
+    fn test_example_config_is_valid() {
+        let config = Config::load(Path::new("scraper.example.toml")).unwrap();
+        assert_eq!(config.start_urls, ["https://www.livescores.com"]);
+        let countries = config.countries.compile("countries").unwrap();
+        assert!(countries.allows("England"));
+        assert!(!countries.allows("Favourites"));
+        assert!(Config::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_errors_name_the_setting() {
+        let config: Config = toml::from_str("[leagues]\ninclude = [\"Premier(\"]").unwrap();
+        let error = config.validate().unwrap_err().to_string();
+        assert!(
+            error.starts_with("Invalid config: leagues.include:"),
+            "{}",
+            error
+        );
+
+        let config: Config = toml::from_str("start_urls = [\"www.livescores.com\"]").unwrap();
+        assert!(config
+            .validate()
+            .unwrap_err()
+            .to_string()
+            .contains("start_urls"));
+        assert!(toml::from_str::<Config>("concurency = 2").is_err());
+
+        let config: Config = toml::from_str("[games]\nmax_error_ratio = 1.5").unwrap();
+        assert!(config
+            .validate()
+            .unwrap_err()
+            .to_string()
+            .contains("games.max_error_ratio"));
+
+        let config: Config = toml::from_str("timezone = \"+05:30\"").unwrap();
+        assert!(config
+            .validate()
+            .unwrap_err()
+            .to_string()
+            .starts_with("Invalid config: timezone:"));
+        let config: Config = toml::from_str("timezone = \"-03:00\"").unwrap();
+        assert_eq!(config.timezone().unwrap().local_minus_utc(), -3 * 3600);
+    }
+
+    #[test]
+    fn test_name_filter() {
+        let filter = Patterns {
+            include: vec!["^Premier".to_owned(), "Cup$".to_owned()],
+            exclude: vec!["Women".to_owned()],
+        }
+        .compile("leagues")
+        .unwrap();
+        assert!(filter.allows("Premier League"));
+        assert!(filter.allows(" FA Cup "));
+        assert!(!filter.allows("Championship"));
+        assert!(!filter.allows("Women's FA Cup"));
+    }
+}
@@ -0,0 +1,413 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use futures::stream::{FuturesUnordered, StreamExt};
+use reqwest::Url;
+use tokio::time::{sleep_until, Instant};
+
+use crate::fetch::FetchError;
+use crate::frontier::Frontier;
+use crate::rate_limit::RateLimiter;
+use crate::retry::{DeadLetter, RetryPolicy, RetryQueue};
+use crate::{Scraper, ScraperTask, ScraperTaskResult, TaskError};
+
+pub struct CrawlerSettings {
+    /// Maximum number of `scripe` calls running at the same time.
+    pub concurrency: usize,
+    /// Minimum time between two requests started against the same host.
+    pub politeness_delay: Duration,
+    /// `politeness_delay` for specific hosts.
+    pub host_delays: HashMap<String, Duration>,
+    /// Requests a host may get at once before the delay applies.
+    pub burst: u32,
+    pub retry: RetryPolicy,
+}
+
+impl Default for CrawlerSettings {
+    fn default() -> Self {
+        CrawlerSettings {
+            concurrency: 4,
+            politeness_delay: Duration::from_secs(1),
+            host_delays: HashMap::new(),
+            burst: 1,
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+pub struct Crawler {
+    scraper: Scraper,
+    settings: CrawlerSettings,
+}
+
+impl Crawler {
+    pub fn new(scraper: Scraper, settings: CrawlerSettings) -> Self {
+        Crawler { scraper, settings }
+    }
+
+    /// Runs the crawl until `queue` is drained and no task is in flight.
+    /// New tasks from successful results are pushed back to the queue before
+    /// `on_result` is called; pages already visited are skipped. Unsuccessful
+    /// results and fetch errors that may go away are retried with backoff,
+    /// tasks that keep failing or can't succeed, like a 404, are returned as
+    /// dead letters. Hosts answering 429 or 503 are fetched from more slowly.
+    pub async fn run<F>(&self, mut queue: Frontier, mut on_result: F) -> Vec<DeadLetter>
+    where
+        F: FnMut(&ScraperTask, Result<ScraperTaskResult, TaskError>),
+    {
+        let mut retries = RetryQueue::default();
+        let mut limiter = RateLimiter::new(
+            self.settings.politeness_delay,
+            self.settings.host_delays.clone(),
+            self.settings.burst,
+        );
+        let concurrency = self.settings.concurrency.max(1);
+        let mut in_flight = FuturesUnordered::new();
+        loop {
+            // Only tasks whose host may be fetched from now take a slot, the
+            // others stay queued and don't hold up the other hosts.
+            while in_flight.len() < concurrency {
+                let now = Instant::now();
+                let next = {
+                    let mut ready =
+                        |task: &ScraperTask| limiter.ready_at(&host_of(task), now) <= now;
+                    retries
+                        .pop_due(now, &mut ready)
+                        .or_else(|| queue.pop_where(&mut ready))
+                };
+                match next {
+                    Some(task) => {
+                        let start = limiter.reserve(&host_of(&task), now);
+                        in_flight.push(scripe_at(&self.scraper, task, start));
+                    }
+                    None => break,
+                }
+            }
+            // A waiting task can only start once a slot is free, so with all
+            // slots taken only a finished task moves the crawl forward.
+            let next_start = if in_flight.len() < concurrency {
+                next_start(&queue, &retries, &mut limiter, Instant::now())
+            } else {
+                None
+            };
+            let finished = match (in_flight.is_empty(), next_start) {
+                (true, None) => break,
+                (true, Some(at)) => {
+                    sleep_until(at).await;
+                    None
+                }
+                (false, None) => in_flight.next().await,
+                (false, Some(at)) => tokio::select! {
+                    finished = in_flight.next() => finished,
+                    _ = sleep_until(at) => None,
+                },
+            };
+            if let Some((task, mut res)) = finished {
+                let host = host_of(&task);
+                match &res {
+                    Err(TaskError::Fetch(FetchError::Status {
+                        status: 429 | 503,
+                        retry_after,
+                        ..
+                    })) => {
+                        limiter.throttled(&host, *retry_after, Instant::now());
+                        println!(
+                            "Slowing down to one request every {:?} for {}",
+                            limiter.interval(&host),
+                            host
+                        );
+                    }
+                    Err(TaskError::Fetch(_)) => {}
+                    _ => limiter.succeeded(&host, Instant::now()),
+                }
+                let failure = match &mut res {
+                    Ok(result) if result.success => {
+                        queue.extend(std::mem::take(&mut result.new_urls));
+                        queue.mark_done(&task);
+                        None
+                    }
+                    Ok(_) => Some(("unsuccessful result".to_owned(), true)),
+                    Err(TaskError::Fetch(e)) => Some((e.to_string(), e.is_retryable())),
+                    Err(_) => {
+                        queue.mark_failed(&task);
+                        None
+                    }
+                };
+                on_result(&task, res);
+                if let Some((error, retryable)) = failure {
+                    let dead_letter = if retryable {
+                        retries.schedule(&self.settings.retry, task, error)
+                    } else {
+                        Some(retries.give_up(task, error))
+                    };
+                    if let Some(dead_letter) = dead_letter {
+                        queue.mark_failed(&dead_letter.task);
+                    }
+                }
+            }
+        }
+        retries.into_dead_letters()
+    }
+}
+
+/// When the first of the queued and retried tasks may start, given their
+/// hosts' pace.
+fn next_start(
+    queue: &Frontier,
+    retries: &RetryQueue,
+    limiter: &mut RateLimiter,
+    now: Instant,
+) -> Option<Instant> {
+    let mut first: Option<Instant> = None;
+    for task in queue.pending() {
+        let at = limiter.ready_at(&host_of(task), now);
+        first = Some(first.map_or(at, |first| first.min(at)));
+    }
+    for (due, task) in retries.waiting() {
+        let at = due.max(limiter.ready_at(&host_of(task), now));
+        first = Some(first.map_or(at, |first| first.min(at)));
+    }
+    first
+}
+
+async fn scripe_at(
+    scraper: &Scraper,
+    task: ScraperTask,
+    start: Instant,
+) -> (ScraperTask, Result<ScraperTaskResult, TaskError>) {
+    sleep_until(start).await;
+    let res = scraper.scripe(&task).await;
+    (task, res)
+}
+
+fn host_of(task: &ScraperTask) -> String {
+    match Url::parse(&task.url) {
+        Ok(url) => url.host_str().unwrap_or_default().to_owned(),
+        Err(_) => task.url.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+    use std::future::{poll_fn, Future};
+    use std::pin::pin;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use futures::future::BoxFuture;
+    use reqwest::Url;
+    use tokio::time::Instant;
+
+    use crate::config::Config;
+    use crate::crawler::{Crawler, CrawlerSettings};
+    use crate::fetch::{FetchError, Fetcher, Request, Response};
+    use crate::frontier::Frontier;
+    use crate::livescores::MatchDetailParser;
+    use crate::retry::RetryPolicy;
+    use crate::{livescores_scraper, ScripingFunction};
+
+    #[derive(Default)]
+    struct Log {
+        /// Host and start of every request, in order.
+        started: Vec<(String, Instant)>,
+        running: usize,
+        max_running: usize,
+        /// Times the crawl was polled, about once per finished request
+        /// unless it busy-loops.
+        polls: usize,
+    }
+
+    /// Answers every request with an empty match page, after the delay set
+    /// for its host. The first request to a host in `fail_once` gets a 500,
+    /// to a host in `throttle_once` a 429 asking to wait a second.
+    #[derive(Clone, Default)]
+    struct Probe {
+        delays: HashMap<String, Duration>,
+        fail_once: HashSet<String>,
+        throttle_once: HashSet<String>,
+        log: Arc<Mutex<Log>>,
+    }
+
+    impl Fetcher for Probe {
+        fn fetch<'a>(
+            &'a self,
+            request: &'a Request,
+        ) -> BoxFuture<'a, Result<Response, FetchError>> {
+            let host = Url::parse(&request.url)
+                .ok()
+                .and_then(|url| url.host_str().map(|host| host.to_owned()))
+                .unwrap_or_default();
+            Box::pin(async move {
+                let first = {
+                    let mut log = self.log.lock().unwrap();
+                    let first = log.started.iter().all(|(started, _)| *started != host);
+                    log.started.push((host.clone(), Instant::now()));
+                    log.running += 1;
+                    log.max_running = log.max_running.max(log.running);
+                    first
+                };
+                if let Some(delay) = self.delays.get(&host) {
+                    tokio::time::sleep(*delay).await;
+                }
+                self.log.lock().unwrap().running -= 1;
+                if first && self.fail_once.contains(&host) {
+                    return Ok(Response::new(500, ""));
+                }
+                if first && self.throttle_once.contains(&host) {
+                    return Ok(Response {
+                        retry_after: Some("1".to_owned()),
+                        ..Response::new(429, "")
+                    });
+                }
+                Ok(Response::new(
+                    200,
+                    "<div data-testid=\"match_detail-incidents\"></div>",
+                ))
+            })
+        }
+    }
+
+    async fn crawl(probe: &Probe, settings: CrawlerSettings, pages: &[(&str, &str)]) -> usize {
+        let scraper = livescores_scraper(&Config::default(), Box::new(probe.clone()));
+        let mut queue = Frontier::default();
+        for (site, href) in pages {
+            queue.push(MatchDetailParser::new_task(site, href));
+        }
+        let mut parsed = 0;
+        let crawler = Crawler::new(scraper, settings);
+        let dead_letters = {
+            let mut run = pin!(crawler.run(queue, |_, res| parsed += res.is_ok() as usize));
+            poll_fn(|cx| {
+                probe.log.lock().unwrap().polls += 1;
+                run.as_mut().poll(cx)
+            })
+            .await
+        };
+        assert!(dead_letters.is_empty(), "{:?}", dead_letters);
+        parsed
+    }
+
+    #[tokio::test]
+    async fn test_crawler_bounds_pages_in_flight() {
+        let delay = Duration::from_millis(50);
+        let probe = Probe {
+            delays: HashMap::from([
+                ("a.test".to_owned(), delay),
+                ("b.test".to_owned(), delay),
+                ("c.test".to_owned(), delay),
+            ]),
+            ..Default::default()
+        };
+        let settings = CrawlerSettings {
+            concurrency: 2,
+            politeness_delay: Duration::ZERO,
+            ..Default::default()
+        };
+        let pages = [
+            ("http://a.test", "/1/"),
+            ("http://b.test", "/2/"),
+            ("http://c.test", "/3/"),
+            ("http://a.test", "/4/"),
+            ("http://b.test", "/5/"),
+            ("http://c.test", "/6/"),
+        ];
+        assert_eq!(crawl(&probe, settings, &pages).await, 6);
+        assert_eq!(probe.log.lock().unwrap().max_running, 2);
+    }
+
+    #[tokio::test]
+    async fn test_crawler_spaces_requests_to_a_host() {
+        let probe = Probe::default();
+        let delay = Duration::from_millis(100);
+        let settings = CrawlerSettings {
+            concurrency: 4,
+            politeness_delay: delay,
+            ..Default::default()
+        };
+        let pages = [
+            ("http://a.test", "/1/"),
+            ("http://a.test", "/2/"),
+            ("http://a.test", "/3/"),
+            ("http://b.test", "/4/"),
+        ];
+        assert_eq!(crawl(&probe, settings, &pages).await, 4);
+
+        let log = probe.log.lock().unwrap();
+        let starts = |host: &str| -> Vec<Instant> {
+            log.started
+                .iter()
+                .filter(|(started_host, _)| started_host == host)
+                .map(|(_, at)| *at)
+                .collect()
+        };
+        let a = starts("a.test");
+        assert_eq!(a.len(), 3);
+        for pair in a.windows(2) {
+            assert!(pair[1] - pair[0] >= delay - Duration::from_millis(5));
+        }
+        // Other hosts don't wait for it.
+        assert!(starts("b.test")[0] - a[0] < delay);
+    }
+
+    #[tokio::test]
+    async fn test_crawler_waits_for_a_slot_when_a_retry_is_due() {
+        let probe = Probe {
+            delays: HashMap::from([("slow.test".to_owned(), Duration::from_millis(300))]),
+            fail_once: HashSet::from(["flaky.test".to_owned()]),
+            ..Default::default()
+        };
+        let settings = CrawlerSettings {
+            concurrency: 1,
+            politeness_delay: Duration::ZERO,
+            retry: RetryPolicy {
+                max_attempts: 2,
+                base_delay: Duration::from_millis(10),
+                max_delay: Duration::from_millis(10),
+            },
+            ..Default::default()
+        };
+        // The flaky page is taken first, fails and is due again while the
+        // slow one holds the only slot.
+        let pages = [("http://slow.test", "/1/"), ("http://flaky.test", "/2/")];
+        assert_eq!(crawl(&probe, settings, &pages).await, 2);
+
+        let log = probe.log.lock().unwrap();
+        let hosts: Vec<&str> = log.started.iter().map(|(host, _)| host.as_str()).collect();
+        assert_eq!(hosts, ["flaky.test", "slow.test", "flaky.test"]);
+        assert!(log.polls < 20, "crawl polled {} times", log.polls);
+    }
+
+    #[tokio::test]
+    async fn test_throttled_host_does_not_hold_up_the_others() {
+        let probe = Probe {
+            throttle_once: HashSet::from(["a.test".to_owned()]),
+            ..Default::default()
+        };
+        let settings = CrawlerSettings {
+            concurrency: 1,
+            politeness_delay: Duration::ZERO,
+            retry: RetryPolicy {
+                max_attempts: 2,
+                base_delay: Duration::from_millis(10),
+                max_delay: Duration::from_millis(10),
+            },
+            ..Default::default()
+        };
+        // The a.test pages are taken first, and the first one is throttled.
+        let pages = [
+            ("http://b.test", "/3/"),
+            ("http://b.test", "/4/"),
+            ("http://a.test", "/1/"),
+            ("http://a.test", "/2/"),
+        ];
+        assert_eq!(crawl(&probe, settings, &pages).await, 4);
+
+        let log = probe.log.lock().unwrap();
+        let hosts: Vec<&str> = log.started.iter().map(|(host, _)| host.as_str()).collect();
+        assert_eq!(hosts, ["a.test", "b.test", "b.test", "a.test", "a.test"]);
+        let throttled_at = log.started[0].1;
+        assert!(log.started[2].1 - throttled_at < Duration::from_millis(500));
+        assert!(log.started[3].1 - throttled_at >= Duration::from_millis(995));
+    }
+}
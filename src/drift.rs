@@ -0,0 +1,284 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::livescores::{GamesParser, LeagueGroupParser, MainParser, MatchDetailParser};
+use crate::{ScraperTask, ScraperTaskResult, TaskData};
+
+/// Selector chains that must find something on every page of a parser.
+const REQUIRED_FIELDS: [(&str, &[&str]); 4] = [
+    ("main", &["main.country"]),
+    ("league_group", &["league_group.league"]),
+    (
+        "games",
+        &[
+            "games.section",
+            "games.date",
+            "games.row",
+            "games.status",
+            "games.home_team",
+            "games.away_team",
+        ],
+    ),
+    (
+        "match_detail",
+        &[
+            "match_detail.incident",
+            "match_detail.icon",
+            "match_detail.time",
+        ],
+    ),
+];
+
+/// What a healthy page yields. A page that parses but falls short was most
+/// likely read with selectors that no longer fit the site's layout.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthChecks {
+    /// Country links expected in the site menu, before the countries filter.
+    pub min_countries: usize,
+    /// League links expected on a country page, before the leagues filter.
+    pub min_leagues: usize,
+    pub min_games: usize,
+    /// Fraction of the game rows of a page that must parse.
+    pub min_parsed_ratio: f64,
+    /// Incidents expected on the page of a finished match, which lists at
+    /// least its substitutions.
+    pub min_incidents: usize,
+    /// Where the suspicious pages and the drift report are written.
+    pub dir: PathBuf,
+}
+
+impl Default for HealthChecks {
+    fn default() -> Self {
+        HealthChecks {
+            min_countries: 20,
+            min_leagues: 1,
+            min_games: 1,
+            min_parsed_ratio: 0.95,
+            min_incidents: 1,
+            dir: PathBuf::from("drift"),
+        }
+    }
+}
+
+/// A result that failed the health checks, with the page it came from.
+#[derive(Debug)]
+pub struct Suspicion {
+    pub issues: Vec<String>,
+    pub html: String,
+}
+
+impl HealthChecks {
+    /// Reasons to distrust `result`, empty when the page looks healthy.
+    pub fn inspect(&self, task: &ScraperTask, result: &ScraperTaskResult) -> Vec<String> {
+        let mut issues = vec![];
+        let fallbacks = &result.fallbacks;
+        let required = REQUIRED_FIELDS
+            .iter()
+            .find(|(parser, _)| *parser == task.name)
+            .map_or(&[][..], |(_, fields)| fields);
+        for field in required {
+            if fallbacks.matched(field) == 0 {
+                issues.push(format!("no selector of {} matched", field));
+            }
+        }
+        let mut expect = |what: &str, found: usize, min: usize| {
+            if found < min {
+                issues.push(format!(
+                    "found {} {}, expected at least {}",
+                    found, what, min
+                ));
+            }
+        };
+        match &result.data {
+            TaskData::Empty if task.name == MainParser::my_name() => {
+                expect(
+                    "countries",
+                    fallbacks.matched("main.country"),
+                    self.min_countries,
+                );
+            }
+            TaskData::LeagueGroup(_) if task.name == LeagueGroupParser::my_name() => {
+                let found = fallbacks.matched("league_group.league");
+                expect("leagues", found, self.min_leagues);
+            }
+            TaskData::Games(page) if task.name == GamesParser::my_name() => {
+                expect("games", page.games.len(), self.min_games);
+                let rows = page.games.len() + result.warnings.len();
+                if rows > 0 {
+                    let ratio = page.games.len() as f64 / rows as f64;
+                    if ratio < self.min_parsed_ratio {
+                        issues.push(format!(
+                            "parsed {} of {} game rows, expected at least {:.0}%",
+                            page.games.len(),
+                            rows,
+                            self.min_parsed_ratio * 100.0
+                        ));
+                    }
+                }
+            }
+            TaskData::MatchDetail(page) if task.name == MatchDetailParser::my_name() => {
+                expect("incidents", page.incidents.len(), self.min_incidents);
+            }
+            _ => {}
+        }
+        issues
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct DriftEntry {
+    url: String,
+    parser: String,
+    issues: Vec<String>,
+    /// Copy of the page, to update the selector profiles against.
+    html: PathBuf,
+    /// Selectors found only by a fallback, a hint of what changed.
+    fallbacks: Vec<String>,
+}
+
+/// Suspicious pages seen during a run.
+#[derive(Debug)]
+pub struct DriftReport {
+    dir: PathBuf,
+    entries: Vec<DriftEntry>,
+}
+
+impl DriftReport {
+    pub fn new(dir: &Path) -> DriftReport {
+        DriftReport {
+            dir: dir.to_owned(),
+            entries: vec![],
+        }
+    }
+
+    /// Saves the page of a suspicious result and adds it to the report.
+    pub fn record(
+        &mut self,
+        task: &ScraperTask,
+        result: &ScraperTaskResult,
+        suspicion: &Suspicion,
+    ) -> std::io::Result<&Path> {
+        std::fs::create_dir_all(&self.dir)?;
+        let html = self
+            .dir
+            .join(format!("{}-{}.html", task.name, self.entries.len() + 1));
+        std::fs::write(&html, &suspicion.html)?;
+        self.entries.push(DriftEntry {
+            url: format!("{}{}", task.url, task.href),
+            parser: task.name.clone(),
+            issues: suspicion.issues.clone(),
+            html,
+            fallbacks: result
+                .fallbacks
+                .fallbacks()
+                .map(|(field, index, selector, _)| format!("{} #{} `{}`", field, index, selector))
+                .collect(),
+        });
+        Ok(&self.entries[self.entries.len() - 1].html)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Writes report.json next to the saved pages, returning its path.
+    pub fn write(&self) -> std::io::Result<PathBuf> {
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join("report.json");
+        let content = serde_json::to_string_pretty(&self.entries).map_err(std::io::Error::from)?;
+        std::fs::write(&path, content)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use scraper::Html;
+
+    use crate::drift::{DriftReport, HealthChecks, Suspicion};
+    use crate::livescores::{GamesParser, MainParser, MatchDetailParser};
+    use crate::ScripingFunction;
+
+    #[test]
+    fn test_healthy_games_page_passes() {
+        let content = std::fs::read_to_string("test_data/games_2023_01_28.html").unwrap();
+        let task = GamesParser::new_task("https://livescores.com", "/");
+        let result = GamesParser::default()
+            .parse(&task, &Html::parse_document(&content))
+            .unwrap();
+        assert_eq!(
+            HealthChecks::default().inspect(&task, &result),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_match_detail_without_incidents_is_suspicious() {
+        let task = MatchDetailParser::new_task(
+            "https://livescores.com",
+            "/football/europa-league-20-21/group-g-2020-2021/leicester-city-vs-aek-athens/316190/",
+        );
+        let document = Html::parse_document(
+            r#"<div data-testid="match_detail-incident_1"><span data-testid="match_detail-incident_1-time">23'</span><svg data-testid="incident_icon-goal"></svg><span data-testid="match_detail-incident_1-home_player">Jamie Vardy</span></div>"#,
+        );
+        let result = MatchDetailParser::default()
+            .parse(&task, &document)
+            .unwrap();
+        assert!(HealthChecks::default().inspect(&task, &result).is_empty());
+
+        let document = Html::parse_document(r#"<div class="Yy"><span>23'</span></div>"#);
+        let result = MatchDetailParser::default()
+            .parse(&task, &document)
+            .unwrap();
+        assert_eq!(
+            HealthChecks::default().inspect(&task, &result),
+            [
+                "no selector of match_detail.incident matched",
+                "no selector of match_detail.icon matched",
+                "no selector of match_detail.time matched",
+                "found 0 incidents, expected at least 1"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_drifted_pages_are_reported() {
+        let task = MainParser::new_task("https://livescores.com", "/");
+        let document = Html::parse_document(
+            r#"<nav><a class="Xx" href="/football/england/">England</a></nav>"#,
+        );
+        let result = MainParser::default().parse(&task, &document).unwrap();
+        let issues = HealthChecks::default().inspect(&task, &result);
+        assert_eq!(
+            issues,
+            [
+                "no selector of main.country matched",
+                "found 0 countries, expected at least 20"
+            ]
+        );
+
+        let dir = std::env::temp_dir().join(format!("drift-{}", std::process::id()));
+        let mut report = DriftReport::new(&dir);
+        let suspicion = Suspicion {
+            issues,
+            html: document.root_element().html(),
+        };
+        let html = report
+            .record(&task, &result, &suspicion)
+            .unwrap()
+            .to_owned();
+        assert_eq!(html, dir.join("main-1.html"));
+        let path = report.write().unwrap();
+        let written: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(written[0]["url"], "https://livescores.com/");
+        assert_eq!(written[0]["issues"].as_array().unwrap().len(), 2);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
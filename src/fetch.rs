@@ -0,0 +1,407 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, RETRY_AFTER};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+/// A GET of `url`, conditional when the validators of a cached copy are given.
+#[derive(Debug, Default, Clone)]
+pub struct Request {
+    pub url: String,
+    pub if_none_match: Option<String>,
+    pub if_modified_since: Option<String>,
+}
+
+impl Request {
+    pub fn get(url: &str) -> Request {
+        Request {
+            url: url.to_owned(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Status, body and cache validators of a fetched page.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    /// How long a throttling server asks us to wait, in seconds or as a date.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<String>,
+}
+
+impl Response {
+    #[cfg(test)]
+    pub fn new(status: u16, body: &str) -> Response {
+        Response {
+            status,
+            body: body.to_owned(),
+            etag: None,
+            last_modified: None,
+            retry_after: None,
+        }
+    }
+}
+
+/// Why a page couldn't be fetched.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FetchError {
+    /// The server answered with a status other than 200 or 304.
+    Status {
+        url: String,
+        status: u16,
+        /// Wait the server asked for with `Retry-After`.
+        retry_after: Option<Duration>,
+    },
+    Timeout {
+        url: String,
+    },
+    /// The host name didn't resolve or the connection failed.
+    Connect {
+        url: String,
+        message: String,
+    },
+    /// The response body couldn't be read or decoded.
+    Body {
+        url: String,
+        message: String,
+    },
+    /// Too many redirects, usually a redirect loop.
+    Redirect {
+        url: String,
+        message: String,
+    },
+    /// The cassette being replayed has no response for the URL.
+    NotRecorded {
+        url: String,
+    },
+    Other(String),
+}
+
+impl FetchError {
+    fn from_reqwest(url: &str, e: reqwest::Error) -> FetchError {
+        let url = url.to_owned();
+        if e.is_timeout() {
+            return FetchError::Timeout { url };
+        }
+        let (redirect, connect, body) = (
+            e.is_redirect(),
+            e.is_connect(),
+            e.is_body() || e.is_decode(),
+        );
+        let message = e.without_url().to_string();
+        if redirect {
+            FetchError::Redirect { url, message }
+        } else if connect {
+            FetchError::Connect { url, message }
+        } else if body {
+            FetchError::Body { url, message }
+        } else {
+            FetchError::Other(format!("{}: {}", url, message))
+        }
+    }
+
+    /// Whether fetching again later may succeed: true for server errors,
+    /// throttling and network trouble, false for missing pages and other
+    /// client errors.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            FetchError::Status { status, .. } => {
+                matches!(status, 408 | 425 | 429 | 500..=599)
+            }
+            FetchError::Redirect { .. } | FetchError::NotRecorded { .. } => false,
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::Status { url, status, .. } => write!(f, "HTTP {} from {}", status, url),
+            FetchError::Timeout { url } => write!(f, "Timed out fetching {}", url),
+            FetchError::Connect { url, message } => {
+                write!(f, "Can't connect to {}: {}", url, message)
+            }
+            FetchError::Body { url, message } => {
+                write!(f, "Can't read the body of {}: {}", url, message)
+            }
+            FetchError::Redirect { url, message } => {
+                write!(f, "Redirect error for {}: {}", url, message)
+            }
+            FetchError::NotRecorded { url } => write!(f, "No recorded response for {}", url),
+            FetchError::Other(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {}
+
+/// Gets pages for the scraper, from the network or from a recording.
+pub trait Fetcher: Send + Sync {
+    fn fetch<'a>(&'a self, request: &'a Request) -> BoxFuture<'a, Result<Response, FetchError>>;
+}
+
+impl<F: Fetcher + ?Sized> Fetcher for Box<F> {
+    fn fetch<'a>(&'a self, request: &'a Request) -> BoxFuture<'a, Result<Response, FetchError>> {
+        (**self).fetch(request)
+    }
+}
+
+pub struct HttpFetcher {
+    client: Client,
+}
+
+impl HttpFetcher {
+    pub fn new(client: Client) -> Self {
+        HttpFetcher { client }
+    }
+}
+
+impl Fetcher for HttpFetcher {
+    fn fetch<'a>(&'a self, request: &'a Request) -> BoxFuture<'a, Result<Response, FetchError>> {
+        Box::pin(async move {
+            let mut builder = self.client.get(&request.url);
+            if let Some(etag) = &request.if_none_match {
+                builder = builder.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(date) = &request.if_modified_since {
+                builder = builder.header(IF_MODIFIED_SINCE, date);
+            }
+            let response = builder
+                .send()
+                .await
+                .map_err(|e| FetchError::from_reqwest(&request.url, e))?;
+            let header = |name| {
+                response
+                    .headers()
+                    .get(name)
+                    .and_then(|value| value.to_str().ok())
+                    .map(|value| value.to_owned())
+            };
+            let etag = header(ETAG);
+            let last_modified = header(LAST_MODIFIED);
+            let retry_after = header(RETRY_AFTER);
+            let status = response.status().as_u16();
+            let body = response
+                .text()
+                .await
+                .map_err(|e| FetchError::from_reqwest(&request.url, e))?;
+            Ok(Response {
+                status,
+                body,
+                etag,
+                last_modified,
+                retry_after,
+            })
+        })
+    }
+}
+
+/// A line of a cassette file.
+#[derive(Serialize, Deserialize)]
+struct Recording {
+    url: String,
+    #[serde(flatten)]
+    response: Response,
+}
+
+/// Passes requests on to another fetcher and appends every response to a
+/// cassette file, to be served by `Replayer` later.
+pub struct Recorder<F> {
+    inner: F,
+    cassette: Mutex<File>,
+}
+
+impl<F: Fetcher> Recorder<F> {
+    pub fn open(inner: F, cassette: &Path) -> std::io::Result<Self> {
+        let cassette = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(cassette)?;
+        Ok(Recorder {
+            inner,
+            cassette: Mutex::new(cassette),
+        })
+    }
+}
+
+impl<F: Fetcher> Fetcher for Recorder<F> {
+    fn fetch<'a>(&'a self, request: &'a Request) -> BoxFuture<'a, Result<Response, FetchError>> {
+        Box::pin(async move {
+            let response = self.inner.fetch(request).await?;
+            let url = &request.url;
+            let line = serde_json::to_string(&Recording {
+                url: url.clone(),
+                response: response.clone(),
+            })
+            .expect("serializable recording");
+            let mut cassette = self.cassette.lock().expect("cassette lock");
+            writeln!(cassette, "{}", line)
+                .map_err(|e| FetchError::Other(format!("Can't record {}: {}", url, e)))?;
+            Ok(response)
+        })
+    }
+}
+
+/// Serves the responses of a cassette without touching the network. A URL
+/// recorded more than once gets its last response.
+#[derive(Default)]
+pub struct Replayer {
+    responses: HashMap<String, Response>,
+}
+
+impl Replayer {
+    pub fn load(cassette: &Path) -> std::io::Result<Self> {
+        let mut replayer = Replayer::default();
+        for line in BufReader::new(File::open(cassette)?).lines() {
+            let recording: Recording = serde_json::from_str(&line?)?;
+            replayer.insert(&recording.url, recording.response);
+        }
+        Ok(replayer)
+    }
+
+    pub fn insert(&mut self, url: &str, response: Response) {
+        self.responses.insert(url.to_owned(), response);
+    }
+}
+
+impl Fetcher for Replayer {
+    fn fetch<'a>(&'a self, request: &'a Request) -> BoxFuture<'a, Result<Response, FetchError>> {
+        Box::pin(async move {
+            self.responses
+                .get(&request.url)
+                .cloned()
+                .ok_or_else(|| FetchError::NotRecorded {
+                    url: request.url.clone(),
+                })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::time::Duration;
+
+    use crate::config::{Config, Patterns};
+    use crate::crawler::{Crawler, CrawlerSettings};
+    use crate::fetch::{FetchError, Fetcher, Recorder, Replayer, Request, Response};
+    use crate::frontier::Frontier;
+    use crate::livescores::LeagueGroupParser;
+    use crate::retry::RetryPolicy;
+    use crate::{livescores_scraper, ScripingFunction, TaskData};
+
+    #[test]
+    fn test_fetch_errors_worth_retrying() {
+        let status = |status| FetchError::Status {
+            url: "https://www.livescores.com/".to_owned(),
+            status,
+            retry_after: None,
+        };
+        assert!(status(503).is_retryable());
+        assert!(status(429).is_retryable());
+        assert!(!status(404).is_retryable());
+        assert_eq!(
+            status(404).to_string(),
+            "HTTP 404 from https://www.livescores.com/"
+        );
+        assert!(FetchError::Timeout {
+            url: "https://www.livescores.com/".to_owned()
+        }
+        .is_retryable());
+        assert!(!FetchError::NotRecorded {
+            url: "https://www.livescores.com/".to_owned()
+        }
+        .is_retryable());
+    }
+
+    #[tokio::test]
+    async fn test_recorded_responses_are_replayed() {
+        let cassette = std::env::temp_dir().join(format!("cassette-{}.jsonl", std::process::id()));
+        let mut source = Replayer::default();
+        source.insert("https://www.livescores.com/", Response::new(503, "busy"));
+        let recorder = Recorder::open(source, &cassette).unwrap();
+        assert_eq!(
+            recorder
+                .fetch(&Request::get("https://www.livescores.com/"))
+                .await
+                .unwrap()
+                .status,
+            503
+        );
+        assert!(recorder
+            .fetch(&Request::get("https://www.livescores.com/x"))
+            .await
+            .is_err());
+
+        let replayer = Replayer::load(&cassette).unwrap();
+        let response = replayer
+            .fetch(&Request::get("https://www.livescores.com/"))
+            .await
+            .unwrap();
+        assert_eq!((response.status, response.body.as_str()), (503, "busy"));
+        std::fs::remove_file(cassette).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_crawl_from_replayed_responses() {
+        let site = "https://www.livescores.com";
+        // The country and games pages of test_data, the match pages weren't
+        // recorded.
+        let replayer = Replayer::load(Path::new("test_data/cassette_england.jsonl")).unwrap();
+        let config = Config {
+            leagues: Patterns {
+                include: vec!["^Inter-Confederation".to_owned()],
+                exclude: vec![],
+            },
+            ..Default::default()
+        };
+        let scraper = livescores_scraper(&config, Box::new(replayer));
+        let settings = CrawlerSettings {
+            politeness_delay: Duration::ZERO,
+            retry: RetryPolicy {
+                max_attempts: 2,
+                base_delay: Duration::from_millis(10),
+                max_delay: Duration::from_millis(10),
+            },
+            ..Default::default()
+        };
+        let mut queue = Frontier::default();
+        queue.push(LeagueGroupParser::new_task(site, "/football/england/"));
+
+        let mut pages = vec![];
+        let mut games = 0;
+        let dead_letters = Crawler::new(scraper, settings)
+            .run(queue, |task, res| {
+                if let Ok(result) = res {
+                    if let TaskData::Games(page) = &result.data {
+                        games += page.games.len();
+                    }
+                    pages.push(task.name.clone());
+                }
+            })
+            .await;
+        assert_eq!(pages, ["league_group", "games"]);
+        assert_eq!(games, 2);
+        // A page missing from the cassette isn't asked for again.
+        assert_eq!(dead_letters.len(), 2);
+        for letter in &dead_letters {
+            assert_eq!(letter.task.name, "match_detail");
+            assert_eq!(letter.attempts, 1);
+            assert!(letter.error.starts_with("No recorded response for"));
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use crate::ScraperTask;
+
+/// Queue of tasks still to be fetched together with the set of pages already
+/// queued during this crawl, so every page is fetched at most once.
+///
+/// When opened on a journal file every change of a task's state is appended
+/// to it, and reopening the same file restores the pending and in-flight
+/// tasks of an interrupted crawl.
+#[derive(Default)]
+pub struct Frontier {
+    pending: Vec<ScraperTask>,
+    visited: HashSet<String>,
+    journal: Option<File>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum JournalEntry {
+    Queued { task: ScraperTask },
+    Started { key: String },
+    Done { key: String },
+    Failed { key: String },
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum TaskState {
+    Pending,
+    InFlight,
+    Done,
+    Failed,
+}
+
+impl Frontier {
+    /// Opens or creates the journal at `path` and replays it. Tasks that were
+    /// pending or in flight when the previous run stopped are queued again.
+    pub fn open(path: &Path) -> std::io::Result<Frontier> {
+        let mut order: Vec<String> = vec![];
+        let mut tasks: HashMap<String, (ScraperTask, TaskState)> = HashMap::new();
+        if path.exists() {
+            for line in BufReader::new(File::open(path)?).lines() {
+                // A crash can leave a torn last line behind, skip it.
+                let entry = match serde_json::from_str::<JournalEntry>(&line?) {
+                    Ok(entry) => entry,
+                    Err(_) => continue,
+                };
+                let (key, state) = match entry {
+                    JournalEntry::Queued { task } => {
+                        let key = canonical_url(&task);
+                        if !tasks.contains_key(&key) {
+                            order.push(key.clone());
+                        }
+                        tasks.insert(key, (task, TaskState::Pending));
+                        continue;
+                    }
+                    JournalEntry::Started { key } => (key, TaskState::InFlight),
+                    JournalEntry::Done { key } => (key, TaskState::Done),
+                    JournalEntry::Failed { key } => (key, TaskState::Failed),
+                };
+                if let Some(entry) = tasks.get_mut(&key) {
+                    entry.1 = state;
+                }
+            }
+        }
+
+        let mut frontier = Frontier::default();
+        for key in order {
+            let (task, state) = tasks.remove(&key).expect("task of replayed key");
+            if state == TaskState::Pending || state == TaskState::InFlight {
+                frontier.pending.push(task);
+            }
+            frontier.visited.insert(key);
+        }
+        frontier.journal = Some(OpenOptions::new().create(true).append(true).open(path)?);
+        Ok(frontier)
+    }
+
+    /// Queues `task` unless its page was already seen. Tasks marked with
+    /// `refresh` are always queued. Returns whether the task was queued.
+    pub fn push(&mut self, task: ScraperTask) -> bool {
+        let first_visit = self.visited.insert(canonical_url(&task));
+        if first_visit || task.refresh {
+            if self.journal.is_some() {
+                self.record(&JournalEntry::Queued { task: task.clone() });
+            }
+            self.pending.push(task);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn extend(&mut self, tasks: Vec<ScraperTask>) {
+        for task in tasks {
+            self.push(task);
+        }
+    }
+
+    /// Takes the next task to fetch and marks it as in flight.
+    #[cfg(test)]
+    pub fn pop(&mut self) -> Option<ScraperTask> {
+        self.pop_where(|_| true)
+    }
+
+    /// Takes the most recently queued task that `ready` accepts and marks it
+    /// as in flight.
+    pub fn pop_where<P>(&mut self, ready: P) -> Option<ScraperTask>
+    where
+        P: FnMut(&ScraperTask) -> bool,
+    {
+        let i = self.pending.iter().rposition(ready)?;
+        let task = self.pending.remove(i);
+        self.record(&JournalEntry::Started {
+            key: canonical_url(&task),
+        });
+        Some(task)
+    }
+
+    /// Tasks still to be fetched.
+    pub fn pending(&self) -> &[ScraperTask] {
+        &self.pending
+    }
+
+    pub fn mark_done(&mut self, task: &ScraperTask) {
+        self.record(&JournalEntry::Done {
+            key: canonical_url(task),
+        });
+    }
+
+    pub fn mark_failed(&mut self, task: &ScraperTask) {
+        self.record(&JournalEntry::Failed {
+            key: canonical_url(task),
+        });
+    }
+
+    fn record(&mut self, entry: &JournalEntry) {
+        if let Some(journal) = &mut self.journal {
+            let line = serde_json::to_string(entry).expect("journal entry serialization");
+            writeln!(journal, "{}", line).expect("journal write failed");
+        }
+    }
+}
+
+/// Normalized `url + href` of a task: fragment removed, query parameters
+/// sorted and trailing slash dropped.
+pub fn canonical_url(task: &ScraperTask) -> String {
+    let joined = Url::parse(&task.url).and_then(|base| base.join(&task.href));
+    let mut url = match joined {
+        Ok(url) => url,
+        Err(_) => return format!("{}{}", task.url, task.href),
+    };
+    url.set_fragment(None);
+
+    let mut query: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+    if query.is_empty() {
+        url.set_query(None);
+    } else {
+        query.sort();
+        url.query_pairs_mut().clear().extend_pairs(query);
+    }
+
+    let path = url.path().trim_end_matches('/').to_owned();
+    if path.is_empty() {
+        url.set_path("/");
+    } else {
+        url.set_path(&path);
+    }
+    url.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::frontier::{canonical_url, Frontier};
+    use crate::livescores::{GamesParser, LeagueGroupParser, MainParser};
+    use crate::ScripingFunction;
+
+    #[test]
+    fn test_canonical_url() {
+        let a = GamesParser::new_task(
+            "https://www.livescores.com",
+            "/football/england/premier-league/?tz=3&b=1#top",
+        );
+        let b = GamesParser::new_task(
+            "https://www.livescores.com/",
+            "/football/england/premier-league?b=1&tz=3",
+        );
+        assert_eq!(canonical_url(&a), canonical_url(&b));
+        assert_eq!(
+            canonical_url(&a),
+            "https://www.livescores.com/football/england/premier-league?b=1&tz=3"
+        );
+        let root = GamesParser::new_task("https://www.livescores.com", "");
+        assert_eq!(canonical_url(&root), "https://www.livescores.com/");
+    }
+
+    #[test]
+    fn test_frontier_skips_visited_pages() {
+        let mut frontier = Frontier::default();
+        assert!(frontier.push(GamesParser::new_task(
+            "https://livescores.com",
+            "/football/spain/"
+        )));
+        assert!(!frontier.push(GamesParser::new_task(
+            "https://livescores.com",
+            "/football/spain"
+        )));
+        assert!(frontier.push(
+            LeagueGroupParser::new_task("https://livescores.com", "/football/spain")
+                .force_refresh()
+        ));
+        let games = frontier.pop_where(|task| task.name == "games").unwrap();
+        assert_eq!(games.href, "/football/spain/");
+        assert_eq!(frontier.pop().unwrap().name, "league_group");
+        assert!(frontier.pop().is_none());
+    }
+
+    #[test]
+    fn test_frontier_resumes_from_journal() {
+        let path = std::env::temp_dir().join(format!("frontier_{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut frontier = Frontier::open(&path).unwrap();
+        frontier.push(MainParser::new_task("https://livescores.com", ""));
+        let main = frontier.pop().unwrap();
+        frontier.extend(vec![
+            LeagueGroupParser::new_task("https://livescores.com", "/football/spain/"),
+            LeagueGroupParser::new_task("https://livescores.com", "/football/italy/"),
+        ]);
+        frontier.mark_done(&main);
+        let italy = frontier.pop().unwrap();
+        frontier.mark_failed(&italy);
+        let spain = frontier.pop().unwrap();
+        assert_eq!(spain.href, "/football/spain/");
+        drop(frontier);
+
+        let mut resumed = Frontier::open(&path).unwrap();
+        let task = resumed.pop().unwrap();
+        assert_eq!(task.href, "/football/spain/");
+        assert_eq!(task.name, "league_group");
+        assert!(resumed.pop().is_none());
+        assert!(!resumed.push(MainParser::new_task("https://livescores.com", "/")));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,46 +1,193 @@
+use std::sync::Arc;
 use std::vec;
 
-use crate::{ScraperTask, ScraperTaskResult, TaskError};
-use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use crate::config::NameFilter;
+use crate::profile::{FallbackUsage, GamesSelectors, MatchDetailSelectors, Profiles};
+use crate::{clock, ScraperTask, ScraperTaskResult, TaskData, TaskError};
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, NaiveTime, TimeZone, Utc};
+use regex::Regex;
 use scraper::{ElementRef, Html, Selector};
 use serde::{Deserialize, Serialize};
 
 use crate::ScripingFunction;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct League {
     name: String,
 }
 
-#[derive(Serialize, Deserialize, Default)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct LeagueGroupPage {
     pub leagues: Vec<League>,
 }
 
-#[derive(Serialize, Deserialize, Default)]
-pub struct MainPage {}
+/// Parses a country page into its leagues and follows the allowed ones.
+pub struct LeagueGroupParser {
+    /// Leagues whose games are crawled.
+    pub league_filter: NameFilter,
+    pub profiles: Arc<Profiles>,
+}
+
+impl Default for LeagueGroupParser {
+    fn default() -> Self {
+        LeagueGroupParser {
+            league_filter: NameFilter::default(),
+            profiles: Profiles::builtin(),
+        }
+    }
+}
+
+/// Titles of the links in the site menu that don't lead to a country.
+pub const NAVIGATION_LINKS: [&str; 3] = ["Home", "Live", "Favourites"];
 
-#[derive(Serialize, Deserialize, Default)]
+/// Follows the countries in the site menu.
+pub struct MainParser {
+    /// Countries in the site menu that are crawled.
+    pub country_filter: NameFilter,
+    pub profiles: Arc<Profiles>,
+}
+
+impl Default for MainParser {
+    fn default() -> Self {
+        MainParser {
+            country_filter: crate::config::Config::default()
+                .countries
+                .compile("countries")
+                .expect("default country patterns"),
+            profiles: Profiles::builtin(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct GamesPage {
     pub games: Vec<Game>,
 }
 
-#[derive(Serialize, Deserialize, Default, Debug)]
+/// Parses the games listed on a league or day page and follows their match
+/// pages.
+pub struct GamesParser {
+    /// Share of game rows that may fail to parse before the whole page is
+    /// reported as failed; failed rows are returned as warnings.
+    pub max_error_ratio: f64,
+    pub profiles: Arc<Profiles>,
+}
+
+impl Default for GamesParser {
+    fn default() -> Self {
+        GamesParser {
+            max_error_ratio: 0.2,
+            profiles: Profiles::builtin(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
 pub struct Team {
     pub name: String,
     pub country: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub enum MatchStatus {
     Scheduled(NaiveTime),
     Postponed,
-    InPlay(i32, i32),
+    Cancelled,
+    /// Minute of play without added time, then the score.
+    InPlay(u32, i32, i32),
+    HalfTime(i32, i32),
+    /// Shoot-out in progress, score after extra time.
+    Penalties(i32, i32),
     Finished(i32, i32),
-    //Maybe other interupted ?
+    /// Score after extra time and the shoot-out result when known. Game rows
+    /// don't show the shoot-out, so parsed games leave it out.
+    FinishedOnPenalties(i32, i32, Option<(i32, i32)>),
+    Abandoned,
+    Interrupted,
+    /// Status code not known to the parser, kept as shown on the site.
+    Unknown(String),
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Flat view of a `MatchStatus` for tables and files with one column per
+/// value.
+pub struct StatusColumns<'a> {
+    pub status: &'a str,
+    pub start_time: Option<NaiveTime>,
+    pub minute: Option<u32>,
+    pub score: Option<(i32, i32)>,
+    pub penalties: Option<(i32, i32)>,
+}
+
+impl MatchStatus {
+    pub fn columns(&self) -> StatusColumns<'_> {
+        let mut columns = StatusColumns {
+            status: "",
+            start_time: None,
+            minute: None,
+            score: None,
+            penalties: None,
+        };
+        columns.status = match self {
+            MatchStatus::Scheduled(time) => {
+                columns.start_time = Some(*time);
+                "scheduled"
+            }
+            MatchStatus::Postponed => "postponed",
+            MatchStatus::Cancelled => "cancelled",
+            MatchStatus::InPlay(at, host, guest) => {
+                columns.minute = Some(*at);
+                columns.score = Some((*host, *guest));
+                "in_play"
+            }
+            MatchStatus::HalfTime(host, guest) => {
+                columns.score = Some((*host, *guest));
+                "half_time"
+            }
+            MatchStatus::Penalties(host, guest) => {
+                columns.score = Some((*host, *guest));
+                "penalties"
+            }
+            MatchStatus::Finished(host, guest) => {
+                columns.score = Some((*host, *guest));
+                "finished"
+            }
+            MatchStatus::FinishedOnPenalties(host, guest, shootout) => {
+                columns.score = Some((*host, *guest));
+                columns.penalties = *shootout;
+                "finished_on_penalties"
+            }
+            MatchStatus::Abandoned => "abandoned",
+            MatchStatus::Interrupted => "interrupted",
+            MatchStatus::Unknown(code) => code.as_str(),
+        };
+        columns
+    }
+
+    /// Inverse of `columns`, for statuses read back from storage.
+    pub fn from_columns(columns: &StatusColumns) -> MatchStatus {
+        let score = columns.score.unwrap_or_default();
+        match columns.status {
+            "scheduled" => match columns.start_time {
+                Some(time) => MatchStatus::Scheduled(time),
+                None => MatchStatus::Unknown(columns.status.to_owned()),
+            },
+            "postponed" => MatchStatus::Postponed,
+            "cancelled" => MatchStatus::Cancelled,
+            "in_play" => MatchStatus::InPlay(columns.minute.unwrap_or_default(), score.0, score.1),
+            "half_time" => MatchStatus::HalfTime(score.0, score.1),
+            "penalties" => MatchStatus::Penalties(score.0, score.1),
+            "finished" => MatchStatus::Finished(score.0, score.1),
+            "finished_on_penalties" => {
+                MatchStatus::FinishedOnPenalties(score.0, score.1, columns.penalties)
+            }
+            "abandoned" => MatchStatus::Abandoned,
+            "interrupted" => MatchStatus::Interrupted,
+            code => MatchStatus::Unknown(code.to_owned()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Game {
     pub status: MatchStatus,
     pub league: String,
@@ -48,9 +195,69 @@ pub struct Game {
     pub start_date: NaiveDate,
     pub host: Team,
     pub guest: Team,
+    /// Match id used by livescores.com in links and `data-testid` attributes.
+    #[serde(default)]
+    pub source_id: Option<u64>,
+    /// Link to the match page, relative to the site root.
+    #[serde(default)]
+    pub detail_href: Option<String>,
+    /// Start of the game in UTC, on the rows that show it.
+    #[serde(default)]
+    pub kickoff: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct MatchDetailPage {
+    pub source_id: Option<u64>,
+    pub incidents: Vec<Incident>,
 }
 
-impl ScripingFunction for LeagueGroupPage {
+/// Parses the incidents of a match page.
+pub struct MatchDetailParser {
+    pub profiles: Arc<Profiles>,
+}
+
+impl Default for MatchDetailParser {
+    fn default() -> Self {
+        MatchDetailParser {
+            profiles: Profiles::builtin(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum IncidentKind {
+    Goal,
+    OwnGoal,
+    PenaltyGoal,
+    PenaltyMissed,
+    YellowCard,
+    SecondYellowCard,
+    RedCard,
+    Substitution,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Side {
+    Home,
+    Away,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Incident {
+    pub kind: IncidentKind,
+    pub side: Side,
+    /// Missing for penalty shoot-out kicks.
+    pub minute: Option<u32>,
+    /// Stoppage time, "2" in "45+2'".
+    pub added_time: Option<u32>,
+    /// Scorer, booked player or the player coming on.
+    pub player: String,
+    /// Assist for goals, the player going off for substitutions.
+    pub other_player: Option<String>,
+}
+
+impl ScripingFunction for LeagueGroupParser {
     fn name(&self) -> &'static str {
         Self::my_name()
     }
@@ -60,24 +267,29 @@ impl ScripingFunction for LeagueGroupPage {
             url: url.to_string(),
             href: href.to_string(),
             name: Self::my_name().to_owned(),
+            refresh: false,
         }
     }
 
     fn parse(&self, task: &ScraperTask, document: &Html) -> Result<ScraperTaskResult, TaskError> {
         let mut new_tasks: Vec<ScraperTask> = vec![];
-        let league_selector = Selector::parse("ul.Dc > li > ul.Dc > li > a").unwrap();
+        let profile = self.profiles.pick(document, |p| &p.league_group.league);
+        let mut fallbacks = FallbackUsage::default();
+        let leagues = profile.league_group.league.all(
+            "league_group.league",
+            document.root_element(),
+            &mut fallbacks,
+        );
 
         let mut data = LeagueGroupPage::default();
-        for element in document.select(&league_selector) {
+        for element in leagues {
             match element.value().attr("href") {
                 Some(href) => {
                     let title = element.text().collect::<String>();
-                    data.leagues.push(League { name: title });
-                    new_tasks.push(ScraperTask {
-                        url: task.url.clone(),
-                        href: href.to_string(),
-                        name: GamesPage::my_name().to_owned(),
-                    });
+                    if self.league_filter.allows(&title) {
+                        data.leagues.push(League { name: title });
+                        new_tasks.push(GamesParser::new_task(&task.url, href));
+                    }
                 }
                 None => {
                     return Err(TaskError::Parsing(document.clone()));
@@ -87,32 +299,42 @@ impl ScripingFunction for LeagueGroupPage {
 
         Ok(ScraperTaskResult {
             url: task.url.clone(),
-            data: serde_json::to_string(&data).unwrap(),
+            data: TaskData::LeagueGroup(data),
             success: true,
             last_update: Utc::now(),
             new_urls: new_tasks,
+            warnings: vec![],
+            fallbacks,
+            suspicious: None,
+            unchanged: false,
         })
     }
 }
 
-impl MainPage {
+impl MainParser {
     pub fn my_name() -> &'static str {
         "main"
     }
 }
-impl LeagueGroupPage {
+impl LeagueGroupParser {
     pub fn my_name() -> &'static str {
         "league_group"
     }
 }
 
-impl GamesPage {
+impl GamesParser {
     pub fn my_name() -> &'static str {
         "games"
     }
 }
 
-impl ScripingFunction for MainPage {
+impl MatchDetailParser {
+    pub fn my_name() -> &'static str {
+        "match_detail"
+    }
+}
+
+impl ScripingFunction for MainParser {
     fn name(&self) -> &'static str {
         Self::my_name()
     }
@@ -122,18 +344,25 @@ impl ScripingFunction for MainPage {
             url: url.to_string(),
             href: href.to_string(),
             name: Self::my_name().to_owned(),
+            refresh: false,
         }
     }
 
     fn parse(&self, task: &ScraperTask, document: &Html) -> Result<ScraperTaskResult, TaskError> {
         let mut new_tasks: Vec<ScraperTask> = vec![];
-        let league_selector = Selector::parse("a.Fc").unwrap();
-        for element in document.select(&league_selector) {
+        let profile = self.profiles.pick(document, |p| &p.main.country);
+        let mut fallbacks = FallbackUsage::default();
+        let countries =
+            profile
+                .main
+                .country
+                .all("main.country", document.root_element(), &mut fallbacks);
+        for element in countries {
             match element.value().attr("href") {
                 Some(href) => {
                     let title = element.text().collect::<String>();
-                    if Self::should_follow(&title) {
-                        new_tasks.push(LeagueGroupPage::new_task(&task.url, href));
+                    if self.country_filter.allows(&title) {
+                        new_tasks.push(LeagueGroupParser::new_task(&task.url, href));
                     }
                 }
                 None => {
@@ -143,15 +372,19 @@ impl ScripingFunction for MainPage {
         }
         Ok(ScraperTaskResult {
             url: task.url.clone(),
-            data: "".to_owned(),
+            data: TaskData::Empty,
             success: true,
             last_update: Utc::now(),
             new_urls: new_tasks,
+            warnings: vec![],
+            fallbacks,
+            suspicious: None,
+            unchanged: false,
         })
     }
 }
 
-impl ScripingFunction for GamesPage {
+impl ScripingFunction for GamesParser {
     fn name(&self) -> &'static str {
         Self::my_name()
     }
@@ -161,66 +394,126 @@ impl ScripingFunction for GamesPage {
             url: url.to_string(),
             href: href.to_string(),
             name: Self::my_name().to_owned(),
+            refresh: false,
         }
     }
 
     fn parse(&self, task: &ScraperTask, document: &Html) -> Result<ScraperTaskResult, TaskError> {
-        let selector = Selector::parse("div.db > div.yf, div.Cf").unwrap();
-        let select_date = Selector::parse("span.cb").unwrap();
-        let select_game = Selector::parse("a.qd").unwrap();
-        let select_stage = Selector::parse("span.fb").unwrap();
-        let select_league = Selector::parse("span.eb").unwrap();
+        let selectors = &self.profiles.pick(document, |p| &p.games.section).games;
 
         let mut data = GamesPage::default();
+        let mut warnings: Vec<TaskError> = vec![];
         let mut date_header: Option<NaiveDate> = None;
+        let mut years = YearInference::new(clock::now().date_naive(), &task.href);
+        let timezone = site_timezone(&task.href);
         let mut league: String = "".to_string();
         let mut league_stage: Option<String> = None;
-        for element in document.select(&selector) {
-            for d in element.select(&select_date) {
+        let mut fallbacks = FallbackUsage::default();
+        let sections =
+            selectors
+                .section
+                .all("games.section", document.root_element(), &mut fallbacks);
+        for element in sections {
+            for d in selectors.date.all("games.date", element, &mut fallbacks) {
                 let date_str = d.text().collect::<String>();
-                date_header = parse_date(&date_str, 2023);
+                date_header = years.date(&date_str);
             }
-            for game_element in element.select(&select_game) {
-                if let Some(league_element) = game_element.select(&select_league).nth(0) {
+            for game_element in selectors.row.all("games.row", element, &mut fallbacks) {
+                if let Some(league_element) =
+                    selectors
+                        .league
+                        .first("games.league", game_element, &mut fallbacks)
+                {
                     league_stage = None;
                     league = league_element.text().collect::<String>();
-                } else if let Some(league_element) = game_element.select(&select_stage).nth(0) {
+                } else if let Some(league_element) =
+                    selectors
+                        .stage
+                        .first("games.stage", game_element, &mut fallbacks)
+                {
                     league_stage = Some(league_element.text().collect::<String>());
                 } else if let Some(start_date) = date_header {
-                    data.games.push(self.parse_game(
+                    match self.parse_game(
+                        selectors,
+                        &mut fallbacks,
                         game_element,
                         &league,
                         &league_stage,
                         start_date,
-                    )?);
+                    ) {
+                        Ok(mut game) => {
+                            if let (None, MatchStatus::Scheduled(time)) =
+                                (game.kickoff, &game.status)
+                            {
+                                game.kickoff = timezone
+                                    .from_local_datetime(&start_date.and_time(*time))
+                                    .single()
+                                    .map(|kickoff| kickoff.with_timezone(&Utc));
+                            }
+                            data.games.push(game);
+                        }
+                        Err(e) => warnings.push(e),
+                    }
                 } else {
-                    return Err(TaskError::Fragment(
+                    warnings.push(TaskError::Fragment(
                         "game element".to_string(),
                         game_element.html(),
                     ));
                 }
             }
         }
+        let rows = data.games.len() + warnings.len();
+        if !warnings.is_empty() && warnings.len() as f64 > self.max_error_ratio * rows as f64 {
+            return Err(TaskError::Rows(warnings));
+        }
+        // Only a finished match has its whole timeline on its page.
+        let new_tasks = data
+            .games
+            .iter()
+            .filter(|game| {
+                matches!(
+                    game.status,
+                    MatchStatus::Finished(..) | MatchStatus::FinishedOnPenalties(..)
+                )
+            })
+            .filter_map(|game| game.detail_href.as_ref())
+            .map(|href| MatchDetailParser::new_task(&task.url, href))
+            .collect();
         Ok(ScraperTaskResult {
             url: task.url.clone(),
-            data: serde_json::to_string(&data).unwrap(),
+            data: TaskData::Games(data),
             success: true,
             last_update: Utc::now(),
-            new_urls: vec![],
+            new_urls: new_tasks,
+            warnings,
+            fallbacks,
+            suspicious: None,
+            unchanged: false,
         })
     }
 }
 
-impl GamesPage {
-    fn parse_score(&self, game_element: ElementRef) -> Result<(i32, i32), TaskError> {
-        let select_score_home = Selector::parse("span.hh").unwrap();
-        let select_score_guest = Selector::parse("span.ih").unwrap();
+impl GamesParser {
+    fn parse_score(
+        &self,
+        selectors: &GamesSelectors,
+        fallbacks: &mut FallbackUsage,
+        game_element: ElementRef,
+    ) -> Result<(i32, i32), TaskError> {
         let mut home = -1;
         let mut away = -1;
-        if let Some(home_score) = game_element.select(&select_score_home).nth(0) {
+        if let Some(home_score) =
+            selectors
+                .home_score
+                .first("games.home_score", game_element, fallbacks)
+        {
             home = home_score.text().collect::<String>().parse().unwrap_or(-1);
         }
-        if let Some(guest_score) = game_element.select(&select_score_guest).nth(0) {
+        if let Some(guest_score) =
+            selectors
+                .away_score
+                .first("games.away_score", game_element, fallbacks)
+        {
             away = guest_score.text().collect::<String>().parse().unwrap_or(-1);
         }
         if home < 0 || away < 0 {
@@ -233,11 +526,22 @@ impl GamesPage {
         }
     }
 
-    fn parse_teams(&self, game_element: ElementRef) -> Result<(String, String), TaskError> {
-        let select_teams = Selector::parse("span.eh").unwrap();
-        if let Some(team) = game_element.select(&select_teams).nth(0) {
+    fn parse_teams(
+        &self,
+        selectors: &GamesSelectors,
+        fallbacks: &mut FallbackUsage,
+        game_element: ElementRef,
+    ) -> Result<(String, String), TaskError> {
+        if let Some(team) = selectors
+            .home_team
+            .first("games.home_team", game_element, fallbacks)
+        {
             let home_team = team.text().collect::<String>();
-            if let Some(team) = game_element.select(&select_teams).nth(1) {
+            if let Some(team) =
+                selectors
+                    .away_team
+                    .first("games.away_team", game_element, fallbacks)
+            {
                 let away_team = team.text().collect::<String>();
                 Ok((home_team, away_team))
             } else {
@@ -254,23 +558,53 @@ impl GamesPage {
         }
     }
 
-    fn parse_game_status(&self, game_element: ElementRef) -> Result<MatchStatus, TaskError> {
-        let select_start_time = Selector::parse("span.Pg").unwrap();
-        if let Some(start_time_element) = game_element.select(&select_start_time).nth(0) {
+    fn parse_game_status(
+        &self,
+        selectors: &GamesSelectors,
+        fallbacks: &mut FallbackUsage,
+        game_element: ElementRef,
+    ) -> Result<MatchStatus, TaskError> {
+        if let Some(start_time_element) =
+            selectors
+                .status
+                .first("games.status", game_element, fallbacks)
+        {
             let c = start_time_element.text().collect::<String>();
-            if c == "FT".to_string() || c == "AET".to_string() || c == "AAW".to_string() {
-                let score = self.parse_score(game_element)?;
-                Ok(MatchStatus::Finished(score.0, score.1))
-            } else {
-                if let Ok(tt) = chrono::NaiveTime::parse_from_str(&c, "%H:%M") {
-                    Ok(MatchStatus::Scheduled(tt))
-                } else {
-                    Err(TaskError::Fragment(
-                        "Parsing game status".to_string(),
-                        game_element.html(),
-                    ))
+            let status = match c.trim() {
+                "FT" | "AET" | "AAW" => {
+                    let score = self.parse_score(selectors, fallbacks, game_element)?;
+                    MatchStatus::Finished(score.0, score.1)
                 }
-            }
+                "AP" => {
+                    let score = self.parse_score(selectors, fallbacks, game_element)?;
+                    MatchStatus::FinishedOnPenalties(score.0, score.1, None)
+                }
+                "HT" => {
+                    let score = self.parse_score(selectors, fallbacks, game_element)?;
+                    MatchStatus::HalfTime(score.0, score.1)
+                }
+                "Pen" | "Pen." => {
+                    let score = self.parse_score(selectors, fallbacks, game_element)?;
+                    MatchStatus::Penalties(score.0, score.1)
+                }
+                "Postp." => MatchStatus::Postponed,
+                "Canc." => MatchStatus::Cancelled,
+                "Aband." => MatchStatus::Abandoned,
+                "Int." | "Susp." => MatchStatus::Interrupted,
+                code => {
+                    if let Ok(tt) = chrono::NaiveTime::parse_from_str(code, "%H:%M") {
+                        MatchStatus::Scheduled(tt)
+                    } else if let Some((Some(minute), _)) =
+                        code.strip_suffix('\'').and_then(parse_minute)
+                    {
+                        let score = self.parse_score(selectors, fallbacks, game_element)?;
+                        MatchStatus::InPlay(minute, score.0, score.1)
+                    } else {
+                        MatchStatus::Unknown(code.to_owned())
+                    }
+                }
+            };
+            Ok(status)
         } else {
             Err(TaskError::Fragment(
                 "Selector select_start_time failed".to_string(),
@@ -281,16 +615,20 @@ impl GamesPage {
 
     fn parse_game(
         &self,
+        selectors: &GamesSelectors,
+        fallbacks: &mut FallbackUsage,
         game_element: ElementRef,
-        league: &String,
+        league: &str,
         league_stage: &Option<String>,
         start_date: NaiveDate,
     ) -> Result<Game, TaskError> {
-        let status = self.parse_game_status(game_element)?;
-        let (home_team, away_team) = self.parse_teams(game_element)?;
+        let status = self.parse_game_status(selectors, fallbacks, game_element)?;
+        let (home_team, away_team) = self.parse_teams(selectors, fallbacks, game_element)?;
+        let (source_id, detail_href) = self.parse_source(game_element);
+        let kickoff = self.parse_kickoff(selectors, fallbacks, game_element);
         let game = Game {
             status,
-            league: league.clone(),
+            league: league.to_owned(),
             stage: league_stage.clone(),
             start_date,
             host: Team {
@@ -301,15 +639,224 @@ impl GamesPage {
                 name: away_team,
                 country: "".into(),
             },
+            source_id,
+            detail_href,
+            kickoff,
         };
         Ok(game)
     }
+
+    /// Kickoff from the favourite button's "football-834464-1676682000000",
+    /// whatever the status shows.
+    fn parse_kickoff(
+        &self,
+        selectors: &GamesSelectors,
+        fallbacks: &mut FallbackUsage,
+        game_element: ElementRef,
+    ) -> Option<DateTime<Utc>> {
+        let element = selectors
+            .kickoff
+            .first("games.kickoff", game_element, fallbacks)?;
+        let millis = element
+            .value()
+            .attr("data-favouritesdetails")?
+            .rsplit('-')
+            .next()?
+            .parse()
+            .ok()?;
+        DateTime::from_timestamp_millis(millis)
+    }
+
+    fn parse_source(&self, game_element: ElementRef) -> (Option<u64>, Option<String>) {
+        let select_link = Selector::parse("a[href]").unwrap();
+        let select_testid = Selector::parse("[data-testid]").unwrap();
+        let href = match game_element.value().attr("href") {
+            Some(href) => Some(href.to_owned()),
+            None => game_element
+                .select(&select_link)
+                .next()
+                .and_then(|link| link.value().attr("href"))
+                .map(|href| href.to_owned()),
+        };
+        // "match_row_time-status_or_time_316190"
+        let id = href.as_deref().and_then(id_from_href).or_else(|| {
+            game_element
+                .select(&select_testid)
+                .filter_map(|e| e.value().attr("data-testid"))
+                .filter_map(|testid| testid.rsplit('_').next())
+                .find_map(|suffix| suffix.parse::<u64>().ok())
+        });
+        (id, href)
+    }
+}
+
+impl ScripingFunction for MatchDetailParser {
+    fn name(&self) -> &'static str {
+        Self::my_name()
+    }
+
+    fn new_task(url: &str, href: &str) -> ScraperTask {
+        ScraperTask {
+            url: url.to_string(),
+            href: href.to_string(),
+            name: Self::my_name().to_owned(),
+            refresh: false,
+        }
+    }
+
+    fn parse(&self, task: &ScraperTask, document: &Html) -> Result<ScraperTaskResult, TaskError> {
+        let selectors = &self
+            .profiles
+            .pick(document, |p| &p.match_detail.incident)
+            .match_detail;
+        let mut data = MatchDetailPage {
+            source_id: id_from_href(&task.href),
+            ..Default::default()
+        };
+        let mut warnings: Vec<TaskError> = vec![];
+        let mut fallbacks = FallbackUsage::default();
+        let incidents = selectors.incident.all(
+            "match_detail.incident",
+            document.root_element(),
+            &mut fallbacks,
+        );
+        for element in incidents {
+            match self.parse_incident(selectors, &mut fallbacks, element) {
+                Ok(incident) => data.incidents.push(incident),
+                Err(e) => warnings.push(e),
+            }
+        }
+        Ok(ScraperTaskResult {
+            url: task.url.clone(),
+            data: TaskData::MatchDetail(data),
+            success: true,
+            last_update: Utc::now(),
+            new_urls: vec![],
+            warnings,
+            fallbacks,
+            suspicious: None,
+            unchanged: false,
+        })
+    }
+}
+
+impl MatchDetailParser {
+    fn parse_incident(
+        &self,
+        selectors: &MatchDetailSelectors,
+        fallbacks: &mut FallbackUsage,
+        element: ElementRef,
+    ) -> Result<Incident, TaskError> {
+        let kind = selectors
+            .icon
+            .first("match_detail.icon", element, fallbacks)
+            .and_then(|icon| icon.value().attr("data-testid"))
+            .and_then(|testid| incident_kind(testid.trim_start_matches("incident_icon-")))
+            .ok_or_else(|| TaskError::Fragment("Incident kind".to_owned(), element.html()))?;
+        let (side, player) = if let Some(player) =
+            selectors
+                .home_player
+                .first("match_detail.home_player", element, fallbacks)
+        {
+            (Side::Home, player)
+        } else if let Some(player) =
+            selectors
+                .away_player
+                .first("match_detail.away_player", element, fallbacks)
+        {
+            (Side::Away, player)
+        } else {
+            return Err(TaskError::Fragment(
+                "Incident player".to_owned(),
+                element.html(),
+            ));
+        };
+        let (minute, added_time) =
+            match selectors
+                .time
+                .first("match_detail.time", element, fallbacks)
+            {
+                Some(time) => parse_minute(&time.text().collect::<String>()).ok_or_else(|| {
+                    TaskError::Fragment("Incident time".to_owned(), element.html())
+                })?,
+                None => (None, None),
+            };
+        Ok(Incident {
+            kind,
+            side,
+            minute,
+            added_time,
+            player: player.text().collect::<String>().trim().to_owned(),
+            other_player: selectors
+                .other_player
+                .first("match_detail.other_player", element, fallbacks)
+                .map(|other| other.text().collect::<String>().trim().to_owned()),
+        })
+    }
+}
+
+fn incident_kind(name: &str) -> Option<IncidentKind> {
+    match name {
+        "goal" => Some(IncidentKind::Goal),
+        "own_goal" => Some(IncidentKind::OwnGoal),
+        "penalty_goal" => Some(IncidentKind::PenaltyGoal),
+        "penalty_missed" => Some(IncidentKind::PenaltyMissed),
+        "yellow_card" => Some(IncidentKind::YellowCard),
+        "yellow_red_card" => Some(IncidentKind::SecondYellowCard),
+        "red_card" => Some(IncidentKind::RedCard),
+        "substitution" => Some(IncidentKind::Substitution),
+        _ => None,
+    }
+}
+
+/// Parses "23'" and "45+2'" into the minute and the added time.
+fn parse_minute(text: &str) -> Option<(Option<u32>, Option<u32>)> {
+    let text = text.trim().trim_end_matches('\'');
+    if text.is_empty() {
+        return Some((None, None));
+    }
+    match text.split_once('+') {
+        Some((minute, added)) => Some((Some(minute.parse().ok()?), Some(added.parse().ok()?))),
+        None => Some((Some(text.parse().ok()?), None)),
+    }
+}
+
+/// Id of the match in a link to its page,
+/// "/football/<league>/<stage>/<host>-vs-<guest>/316190/?tz=3".
+fn id_from_href(href: &str) -> Option<u64> {
+    href.split(['?', '#'])
+        .next()
+        .and_then(|path| path.trim_end_matches('/').rsplit('/').next())
+        .and_then(|segment| segment.parse::<u64>().ok())
+}
+
+/// Timezone the page was rendered in, taken from the `tz` query parameter
+/// (hours from UTC) the scraper sends with every request.
+pub fn site_timezone(href: &str) -> FixedOffset {
+    let utc = FixedOffset::east_opt(0).unwrap();
+    let query = match href.split('#').next().and_then(|h| h.split_once('?')) {
+        Some((_, query)) => query,
+        None => return utc,
+    };
+    query
+        .split('&')
+        .filter_map(|param| param.strip_prefix("tz="))
+        .filter_map(|hours| hours.parse::<i32>().ok())
+        .find_map(|hours| FixedOffset::east_opt(hours * 3600))
+        .unwrap_or(utc)
 }
 
 fn parse_date(date_str: &str, default_year: i32) -> Option<NaiveDate> {
+    let (month, day, year) = parse_date_parts(date_str);
+    NaiveDate::from_ymd_opt(year.unwrap_or(default_year), month, day)
+}
+
+/// Splits "October 11, 2022" or "February 19" into month, day and the year
+/// when the header shows one.
+fn parse_date_parts(date_str: &str) -> (u32, u32, Option<i32>) {
     let mut day: u32 = 0;
     let mut month: u32 = 0;
-    let mut year = default_year;
+    let mut year = None;
     for el in date_str.split([' ', ',']).enumerate() {
         if el.0 == 1 {
             day = el.1.parse::<u32>().unwrap_or(0);
@@ -328,52 +875,106 @@ fn parse_date(date_str: &str, default_year: i32) -> Option<NaiveDate> {
                 "November",
                 "December",
             ];
-            for i in 0..12 {
-                if el.1 == months[i] {
-                    month = (i + 1) as u32;
-                    break;
-                }
+            if let Some(i) = months.iter().position(|m| *m == el.1) {
+                month = (i + 1) as u32;
             }
         } else if el.0 == 3 {
-            year = el.1.parse::<i32>().unwrap_or(default_year);
+            year = el.1.parse::<i32>().ok();
         }
     }
-    NaiveDate::from_ymd_opt(year, month, day)
+    (month, day, year)
 }
 
-impl MainPage {
-    fn should_follow(title: &String) -> bool {
-        let exclude_list = ["Home", "Live", "Favourites"].map(|s| s.to_string());
-        for item in exclude_list {
-            if item.eq(title) {
-                return false;
+/// Picks the year of date headers that don't show one. In order of
+/// preference the year comes from the previous header on the page, the
+/// season in the page URL and finally the crawl date.
+struct YearInference {
+    crawl_date: NaiveDate,
+    season: Option<(i32, i32)>,
+    previous: Option<NaiveDate>,
+}
+
+impl YearInference {
+    fn new(crawl_date: NaiveDate, href: &str) -> Self {
+        YearInference {
+            crawl_date,
+            season: parse_season(href),
+            previous: None,
+        }
+    }
+
+    fn date(&mut self, date_str: &str) -> Option<NaiveDate> {
+        let (month, day, year) = parse_date_parts(date_str);
+        let date = match (year, self.previous, self.season) {
+            (Some(year), _, _) => NaiveDate::from_ymd_opt(year, month, day),
+            // Headers are in order, so the closest candidate to the previous
+            // one handles the December to January rollover in both directions.
+            (None, Some(previous), _) => closest_date(month, day, previous),
+            (None, None, Some((first, second))) => {
+                parse_date(date_str, if month >= 7 { first } else { second })
             }
+            (None, None, None) => closest_date(month, day, self.crawl_date),
+        };
+        if date.is_some() {
+            self.previous = date;
         }
-        true
+        date
     }
 }
 
+fn closest_date(month: u32, day: u32, reference: NaiveDate) -> Option<NaiveDate> {
+    let year = reference.year();
+    [year - 1, year, year + 1]
+        .iter()
+        .filter_map(|year| NaiveDate::from_ymd_opt(*year, month, day))
+        .min_by_key(|date| (*date - reference).num_days().abs())
+}
+
+/// Season from a league URL: "europa-league-20-21" or "2020-2021" span two
+/// years, "world-cup-2022" a single one.
+fn parse_season(href: &str) -> Option<(i32, i32)> {
+    let two_years = Regex::new(r"(?:^|[-/])(\d{2}|\d{4})-(\d{2}|\d{4})(?:[-/?#]|$)").unwrap();
+    let one_year = Regex::new(r"(?:^|[-/])((?:19|20)\d{2})(?:[-/?#]|$)").unwrap();
+    let full_year = |year: &str| -> Option<i32> {
+        let year: i32 = year.parse().ok()?;
+        Some(if year < 100 { 2000 + year } else { year })
+    };
+    if let Some(c) = two_years.captures(href) {
+        let first = full_year(&c[1])?;
+        let second = full_year(&c[2])?;
+        if second == first + 1 {
+            return Some((first, second));
+        }
+    }
+    let year = full_year(&one_year.captures(href)?[1])?;
+    Some((year, year))
+}
+
 #[cfg(test)]
 mod tests {
     use scraper::Html;
 
-    use crate::livescores::{parse_date, GamesPage, LeagueGroupPage, MatchStatus};
-    use crate::ScripingFunction;
-    use crate::{ScraperTask, ScraperTaskResult, TaskError};
-    use chrono::NaiveDate;
+    use crate::livescores::{
+        parse_date, parse_season, site_timezone, GamesParser, IncidentKind, LeagueGroupParser,
+        MatchDetailParser, MatchStatus, Side, YearInference,
+    };
+    use crate::profile::FallbackUsage;
+    use crate::{clock, ScripingFunction, TaskData, TaskError};
+    use chrono::{NaiveDate, TimeZone, Utc};
 
     #[test]
     fn test_parse_league_group_page() {
         let filename = "test_data/parse_country.html";
-        let content = std::fs::read_to_string(&filename).expect("cant read file");
+        let content = std::fs::read_to_string(filename).expect("cant read file");
         let document = Html::parse_document(&content);
-        let league_group: LeagueGroupPage = Default::default();
-        let task = LeagueGroupPage::new_task("https://livescores.com", "/");
+        let league_group = LeagueGroupParser::default();
+        let task = LeagueGroupParser::new_task("https://livescores.com", "/");
         let p = league_group
             .parse(&task, &document)
             .expect("Parsing error :(");
-        let data: LeagueGroupPage =
-            serde_json::from_str(&p.data).expect("parsing error from serde json");
+        let TaskData::LeagueGroup(data) = p.data else {
+            panic!("expected league group payload");
+        };
         assert_eq!(data.leagues.len(), 14);
         assert_eq!(
             data.leagues[0].name,
@@ -392,17 +993,18 @@ mod tests {
     #[test]
     fn test_parse_games_page() {
         let filename = "test_data/parse_country.html";
-        let content = std::fs::read_to_string(&filename).expect("cant read file");
+        let content = std::fs::read_to_string(filename).expect("cant read file");
         let document = Html::parse_document(&content);
-        let games_page: GamesPage = Default::default();
-        let task = GamesPage::new_task("https://livescores.com", "/");
+        let games_page = GamesParser::default();
+        let task = GamesParser::new_task("https://livescores.com", "/");
         let p = games_page
             .parse(&task, &document)
             .expect("Parsing error :(");
-        let data: GamesPage = serde_json::from_str(&p.data).expect("parsing error from serde json");
+        let TaskData::Games(data) = p.data else {
+            panic!("expected games payload");
+        };
         assert_eq!(data.games.len(), 53);
-        let mut i = 0;
-        for game in &data.games {
+        for (i, game) in data.games.iter().enumerate() {
             println!(
                 "{:3} {:20} {:13?} {:10?} {} {} {}",
                 i,
@@ -413,8 +1015,16 @@ mod tests {
                 game.host.name,
                 game.guest.name
             );
-            i = i + 1;
         }
+        let senegal = data
+            .games
+            .iter()
+            .find(|game| game.host.name == "Senegal Women")
+            .unwrap();
+        assert_eq!(
+            senegal.kickoff,
+            Some(Utc.with_ymd_and_hms(2023, 2, 18, 1, 0, 0).unwrap())
+        );
         assert_eq!(data.games[36].status, MatchStatus::Finished(0, 8));
         assert_eq!(data.games[36].host.name, "Bulgaria Women".to_owned());
         assert_eq!(data.games[36].guest.name, "Germany Women".to_owned());
@@ -431,14 +1041,16 @@ mod tests {
     #[test]
     fn test_parse_games_2023_01_28() {
         let filename = "test_data/games_2023_01_28.html";
-        let content = std::fs::read_to_string(&filename).expect("cant read file");
+        let content = std::fs::read_to_string(filename).expect("cant read file");
         let document = Html::parse_document(&content);
-        let games_page: GamesPage = Default::default();
-        let task = GamesPage::new_task("https://livescores.com", "/");
+        let games_page = GamesParser::default();
+        let task = GamesParser::new_task("https://livescores.com", "/");
         let p = games_page
             .parse(&task, &document)
             .expect("Parsing error :(");
-        let data: GamesPage = serde_json::from_str(&p.data).expect("parsing error from serde json");
+        let TaskData::Games(data) = p.data else {
+            panic!("expected games payload");
+        };
         assert_eq!(data.games.len(), 2);
     }
 
@@ -466,20 +1078,132 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_year_inference() {
+        let crawl_date = NaiveDate::from_ymd_opt(2024, 1, 3).unwrap();
+        let mut years = YearInference::new(crawl_date, "/football/england/premier-league/?tz=3");
+        assert_eq!(
+            years.date("December 30"),
+            NaiveDate::from_ymd_opt(2023, 12, 30)
+        );
+        assert_eq!(years.date("January 2"), NaiveDate::from_ymd_opt(2024, 1, 2));
+        assert_eq!(
+            years.date("December 10, 2020"),
+            NaiveDate::from_ymd_opt(2020, 12, 10)
+        );
+        assert_eq!(years.date("January 5"), NaiveDate::from_ymd_opt(2021, 1, 5));
+
+        let mut years = YearInference::new(crawl_date, "/football/europa-league-20-21/group-g/");
+        assert_eq!(
+            years.date("February 18"),
+            NaiveDate::from_ymd_opt(2021, 2, 18)
+        );
+        let mut years = YearInference::new(crawl_date, "/football/europa-league-20-21/group-g/");
+        assert_eq!(
+            years.date("October 22"),
+            NaiveDate::from_ymd_opt(2020, 10, 22)
+        );
+    }
+
+    #[test]
+    fn test_games_page_infers_year_from_crawl_date() {
+        let content = r#"<div class="db"><div class="yf"><span class="cb">December 30</span></div><div class="yf"><a class="qd" href="/football/england/"><span class="eb">Premier League</span></a></div><div class="yf"><a href="/football/england/premier-league/everton-vs-manchester-city/1/" class="qd"><span class="Pg Lg">20:00</span><span class="eh">Everton</span><span class="hh">?</span><span class="ih">?</span><span class="eh">Manchester City</span></a></div></div>"#;
+        let document = Html::parse_document(content);
+        let task = GamesParser::new_task("https://livescores.com", "/football/england/");
+        let crawled_at = Utc.with_ymd_and_hms(2024, 1, 3, 12, 0, 0).unwrap();
+        let p = clock::with_time(crawled_at, || {
+            GamesParser::default().parse(&task, &document)
+        })
+        .expect("Parsing error :(");
+        let TaskData::Games(data) = p.data else {
+            panic!("expected games payload");
+        };
+        assert_eq!(
+            data.games[0].start_date,
+            NaiveDate::from_ymd_opt(2023, 12, 30).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_kickoff_in_utc() {
+        let content = r#"<div class="db"><div class="yf"><span class="cb">April 28, 2024</span></div><div class="yf"><a class="qd" href="/football/england/"><span class="eb">Premier League</span></a></div><div class="yf"><a href="/football/england/premier-league/tottenham-hotspur-vs-arsenal/968096/?tz=3" class="qd"><span class="Pg Lg">16:00</span><span class="eh">Tottenham Hotspur</span><span class="hh">?</span><span class="ih">?</span><span class="eh">Arsenal</span></a></div></div>"#;
+        let document = Html::parse_document(content);
+        let task = GamesParser::new_task("https://livescores.com", "/football/england/?tz=3");
+        let p = GamesParser::default()
+            .parse(&task, &document)
+            .expect("Parsing error :(");
+        let TaskData::Games(data) = p.data else {
+            panic!("expected games payload");
+        };
+        assert_eq!(
+            data.games[0].kickoff,
+            Some(Utc.with_ymd_and_hms(2024, 4, 28, 13, 0, 0).unwrap())
+        );
+        assert_eq!(site_timezone("/?tz=-3").local_minus_utc(), -10800);
+        assert_eq!(site_timezone("/football/").local_minus_utc(), 0);
+    }
+
+    #[test]
+    fn test_games_page_keeps_valid_rows() {
+        let content = r#"<div class="db"><div class="yf"><span class="cb">December 10, 2020</span></div><div class="yf"><a class="qd" href="/football/europa-league-20-21/"><span class="eb">Europa League 20/21</span></a></div>
+            <div class="yf"><a href="/football/europa-league-20-21/group-g/leicester-city-vs-aek-athens/316190/" class="qd"><span class="Pg Lg">FT</span><span class="eh">Leicester City</span><span class="hh">2</span><span class="ih">0</span><span class="eh">AEK Athens</span></a></div>
+            <div class="yf"><a href="/football/europa-league-20-21/group-g/sc-braga-vs-zorya/316191/" class="qd"><span class="Pg Lg">FT</span><span class="eh">SC Braga</span><span class="hh">2</span><span class="ih">0</span></a></div>
+            <div class="yf"><a href="/football/europa-league-20-21/group-g/zorya-vs-aek-athens/316192/" class="qd"><span class="Pg Lg">FT</span><span class="eh">Zorya</span><span class="hh">1</span><span class="ih">4</span><span class="eh">AEK Athens</span></a></div></div>"#;
+        let document = Html::parse_document(content);
+        let task = GamesParser::new_task("https://livescores.com", "/");
+
+        let strict = GamesParser::default();
+        match strict.parse(&task, &document) {
+            Err(TaskError::Rows(errors)) => match &errors[..] {
+                [TaskError::Fragment(name, _)] => assert_eq!(name, "Parse game away team"),
+                _ => panic!("expected the bad row's error, got {:?}", errors),
+            },
+            _ => panic!("one bad row in three should fail the page"),
+        }
+
+        let lenient = GamesParser {
+            max_error_ratio: 0.5,
+            ..Default::default()
+        };
+        let p = lenient.parse(&task, &document).expect("Parsing error :(");
+        let TaskData::Games(data) = p.data else {
+            panic!("expected games payload");
+        };
+        assert_eq!(data.games.len(), 2);
+        assert_eq!(data.games[1].host.name, "Zorya");
+        assert_eq!(p.warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_season() {
+        assert_eq!(
+            parse_season("/football/europa-league-20-21/group-g-2020-2021/"),
+            Some((2020, 2021))
+        );
+        assert_eq!(
+            parse_season("/football/world-cup-2022/"),
+            Some((2022, 2022))
+        );
+        assert_eq!(parse_season("/football/england/premier-league/?tz=3"), None);
+    }
+
     #[test]
     fn test_parse_game_fragment() {
         let fragment = r#"<a class="qd" href="/football/europa-league-20-21/qualification-preliminary-round/lincoln-red-imps-fc-vs-fc-prishtina/326775/"><div class="Xg"><span class="Kg"><span data-testid="match_row_time-status_or_time_326775" class="Pg Lg">AAW</span></span><span class="bh"><span class="ch"><span data-testid="football_match_row-home_team_326775" class="eh">Lincoln Red Imps FC</span></span><span class="Zg"><span data-testid="football_match_row-home_score_326775" class="hh">3</span><span class="jh"> <!-- -->-<!-- --> </span><span class="ih" data-testid="football_match_row-away_score_326775">0</span></span><span class="dh"><span data-testid="football_match_row-away_team_326775" class="eh">FC Prishtina</span></span></span></div></a>"#;
         let doc = Html::parse_fragment(fragment);
-        let g = GamesPage::default();
+        let g = GamesParser::default();
         let game = g
             .parse_game(
+                &g.profiles.newest().games,
+                &mut FallbackUsage::default(),
                 doc.root_element(),
-                &"Europa League".to_string(),
+                "Europa League",
                 &None,
                 NaiveDate::from_ymd_opt(2020, 8, 22).unwrap(),
             )
             .unwrap();
         assert_eq!(game.status, MatchStatus::Finished(3, 0));
+        assert_eq!(game.source_id, Some(326775));
         assert_eq!(game.host.name, "Lincoln Red Imps FC".to_owned());
         assert_eq!(game.guest.name, "FC Prishtina".to_owned());
         assert_eq!(
@@ -491,16 +1215,26 @@ mod tests {
     fn test_parse_game_fragment2() {
         let fragment = r#"<a href="/football/europa-league-20-21/group-g-2020-2021/leicester-city-vs-aek-athens/316190/" class="qd"><div class="Xg"><span class="Kg"><span class="Pg Lg" data-testid="match_row_time-status_or_time_316190">FT</span></span><span class="bh"><span class="ch"><span class="eh" data-testid="football_match_row-home_team_316190">Leicester City</span></span><span class="Zg"><span class="hh" data-testid="football_match_row-home_score_316190">2</span><span class="jh"> <!-- -->-<!-- --> </span><span data-testid="football_match_row-away_score_316190" class="ih">0</span></span><span class="dh"><span data-testid="football_match_row-away_team_316190" class="eh">AEK Athens</span></span></span></div></a>"#;
         let doc = Html::parse_fragment(fragment);
-        let g = GamesPage::default();
+        let g = GamesParser::default();
         let game = g
             .parse_game(
+                &g.profiles.newest().games,
+                &mut FallbackUsage::default(),
                 doc.root_element(),
-                &"Europa League".to_string(),
+                "Europa League",
                 &None,
                 NaiveDate::from_ymd_opt(2020, 8, 22).unwrap(),
             )
             .unwrap();
         assert_eq!(game.status, MatchStatus::Finished(2, 0));
+        assert_eq!(game.source_id, Some(316190));
+        assert_eq!(
+            game.detail_href,
+            Some(
+                "/football/europa-league-20-21/group-g-2020-2021/leicester-city-vs-aek-athens/316190/"
+                    .to_owned()
+            )
+        );
         assert_eq!(game.host.name, "Leicester City".to_owned());
         assert_eq!(game.guest.name, "AEK Athens".to_owned());
         assert_eq!(
@@ -508,4 +1242,125 @@ mod tests {
             NaiveDate::from_ymd_opt(2020, 8, 22).unwrap()
         );
     }
+
+    #[test]
+    fn test_games_page_follows_match_links() {
+        let content = r#"<div class="db"><div class="yf"><span class="cb">December 10, 2020</span></div><div class="yf"><a class="qd" href="/football/europa-league-20-21/"><span class="eb">Europa League 20/21</span></a></div><div class="yf"><a href="/football/europa-league-20-21/group-g-2020-2021/leicester-city-vs-aek-athens/316190/" class="qd"><span class="Pg Lg">FT</span><span class="eh">Leicester City</span><span class="hh">2</span><span class="ih">0</span><span class="eh">AEK Athens</span></a></div><div class="yf"><a href="/football/europa-league-20-21/group-g-2020-2021/sc-braga-vs-zorya/316191/" class="qd"><span class="Pg Lg">21:00</span><span class="eh">SC Braga</span><span class="hh">?</span><span class="ih">?</span><span class="eh">Zorya</span></a></div></div>"#;
+        let document = Html::parse_document(content);
+        let task = GamesParser::new_task("https://livescores.com", "/");
+        let p = GamesParser::default()
+            .parse(&task, &document)
+            .expect("Parsing error :(");
+        // The scheduled game has no timeline yet.
+        assert_eq!(p.new_urls.len(), 1);
+        assert_eq!(p.new_urls[0].name, "match_detail");
+        assert_eq!(
+            p.new_urls[0].href,
+            "/football/europa-league-20-21/group-g-2020-2021/leicester-city-vs-aek-athens/316190/"
+        );
+    }
+
+    #[test]
+    fn test_games_page_reports_fallbacks() {
+        let content = std::fs::read_to_string("test_data/games_2023_01_28.html").unwrap();
+        let task = GamesParser::new_task("https://livescores.com", "/");
+        let p = GamesParser::default()
+            .parse(&task, &Html::parse_document(&content))
+            .unwrap();
+        assert_eq!(p.fallbacks.count("games.status", 0), 2);
+        assert_eq!(p.fallbacks.fallbacks().count(), 0);
+
+        // Rows without data-testid attributes are found by their classes.
+        let content = r#"<div class="db"><div class="yf"><span class="cb">December 10, 2020</span></div><div class="yf"><a class="qd" href="/football/europa-league-20-21/"><span class="eb">Europa League 20/21</span></a></div><div class="yf"><a href="/football/europa-league-20-21/group-g-2020-2021/leicester-city-vs-aek-athens/316190/" class="qd"><span class="Pg Lg">FT</span><span class="eh">Leicester City</span><span class="hh">2</span><span class="ih">0</span><span class="eh">AEK Athens</span></a></div></div>"#;
+        let p = GamesParser::default()
+            .parse(&task, &Html::parse_document(content))
+            .unwrap();
+        let fallbacks: Vec<_> = p.fallbacks.fallbacks().collect();
+        assert!(fallbacks.contains(&("games.status", 1, "span.Pg", 1)));
+        assert!(fallbacks.contains(&("games.away_team", 1, "span.eh #1", 1)));
+    }
+
+    #[test]
+    fn test_parse_match_detail_page() {
+        let content = r#"<div data-testid="match_detail-incidents">
+            <div data-testid="match_detail-incident_1"><span data-testid="match_detail-incident_1-time">23'</span><svg data-testid="incident_icon-goal"></svg><span data-testid="match_detail-incident_1-home_player">Jamie Vardy</span><span data-testid="match_detail-incident_1-other_player">James Maddison</span></div>
+            <div data-testid="match_detail-incident_2"><span data-testid="match_detail-incident_2-time">45+2'</span><svg data-testid="incident_icon-yellow_card"></svg><span data-testid="match_detail-incident_2-away_player">Marko Livaja</span></div>
+            <div data-testid="match_detail-incident_3"><span data-testid="match_detail-incident_3-time">67'</span><svg data-testid="incident_icon-substitution"></svg><span data-testid="match_detail-incident_3-home_player">Ayoze Perez</span><span data-testid="match_detail-incident_3-other_player">Harvey Barnes</span></div>
+            <div data-testid="match_detail-incident_4"><svg data-testid="incident_icon-penalty_missed"></svg><span data-testid="match_detail-incident_4-away_player">Nelson Oliveira</span></div>
+            <div data-testid="match_detail-incident_5"><span data-testid="match_detail-incident_5-time">90'</span><svg data-testid="incident_icon-goal"></svg></div>
+        </div>"#;
+        let document = Html::parse_document(content);
+        let task = MatchDetailParser::new_task(
+            "https://livescores.com",
+            "/football/europa-league-20-21/group-g-2020-2021/leicester-city-vs-aek-athens/316190/",
+        );
+        let p = MatchDetailParser::default()
+            .parse(&task, &document)
+            .expect("Parsing error :(");
+        let TaskData::MatchDetail(data) = p.data else {
+            panic!("expected match detail payload");
+        };
+        assert_eq!(data.source_id, Some(316190));
+        assert_eq!(data.incidents.len(), 4);
+        assert_eq!(data.incidents[0].kind, IncidentKind::Goal);
+        assert_eq!(data.incidents[0].side, Side::Home);
+        assert_eq!(data.incidents[0].minute, Some(23));
+        assert_eq!(data.incidents[0].player, "Jamie Vardy");
+        assert_eq!(
+            data.incidents[0].other_player,
+            Some("James Maddison".to_owned())
+        );
+        assert_eq!(data.incidents[1].kind, IncidentKind::YellowCard);
+        assert_eq!(data.incidents[1].side, Side::Away);
+        assert_eq!(data.incidents[1].minute, Some(45));
+        assert_eq!(data.incidents[1].added_time, Some(2));
+        assert_eq!(data.incidents[2].kind, IncidentKind::Substitution);
+        assert_eq!(data.incidents[3].kind, IncidentKind::PenaltyMissed);
+        assert_eq!(data.incidents[3].minute, None);
+        // The incident without a player is reported, the others kept.
+        assert_eq!(p.warnings.len(), 1);
+        assert!(
+            matches!(&p.warnings[0], TaskError::Fragment(what, _) if what == "Incident player")
+        );
+    }
+
+    fn game_row(status: &str) -> String {
+        format!(
+            r#"<a class="qd" href="/football/champions-league/final/real-madrid-vs-liverpool/1/"><span class="Pg Lg">{}</span><span class="eh">Real Madrid</span><span class="hh">1</span><span class="ih">1</span><span class="eh">Liverpool</span></a>"#,
+            status
+        )
+    }
+
+    #[test]
+    fn test_parse_game_status_codes() {
+        let g = GamesParser::default();
+        let date = NaiveDate::from_ymd_opt(2022, 5, 28).unwrap();
+        let cases = [
+            ("HT", MatchStatus::HalfTime(1, 1)),
+            ("45+2'", MatchStatus::InPlay(45, 1, 1)),
+            ("78'", MatchStatus::InPlay(78, 1, 1)),
+            ("Pen.", MatchStatus::Penalties(1, 1)),
+            ("AET", MatchStatus::Finished(1, 1)),
+            ("AP", MatchStatus::FinishedOnPenalties(1, 1, None)),
+            ("Postp.", MatchStatus::Postponed),
+            ("Canc.", MatchStatus::Cancelled),
+            ("Aband.", MatchStatus::Abandoned),
+            ("Int.", MatchStatus::Interrupted),
+            ("TBA", MatchStatus::Unknown("TBA".to_owned())),
+        ];
+        for (code, expected) in cases {
+            let doc = Html::parse_fragment(&game_row(code));
+            let game = g
+                .parse_game(
+                    &g.profiles.newest().games,
+                    &mut FallbackUsage::default(),
+                    doc.root_element(),
+                    "Champions League",
+                    &None,
+                    date,
+                )
+                .unwrap();
+            assert_eq!(game.status, expected, "status code {}", code);
+        }
+    }
 }
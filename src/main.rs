@@ -1,29 +1,110 @@
-use chrono::{DateTime, Utc};
-use livescores::{GamesPage, MainPage};
-use reqwest::{Client, StatusCode};
+use archive::Archive;
+use cache::{CachingFetcher, NOT_MODIFIED};
+use chrono::{DateTime, FixedOffset};
+use clap::Parser;
+use cli::{Cli, Command, OutputArgs};
+use config::{ArchiveConfig, Config, OutputConfig};
+use crawler::{Crawler, CrawlerSettings};
+use drift::{DriftReport, HealthChecks, Suspicion};
+use fetch::{FetchError, Fetcher, HttpFetcher, Recorder, Replayer, Request};
+use frontier::Frontier;
+use livescores::{
+    GamesPage, GamesParser, LeagueGroupPage, LeagueGroupParser, MainParser, MatchDetailPage,
+    MatchDetailParser,
+};
+use profile::{FallbackUsage, Profiles};
+use rate_limit::parse_retry_after;
+use reparse::{diff_games, GameChange};
+use reqwest::{Client, StatusCode, Url};
+use retry::write_dead_letters;
 use scraper::Html;
-use std::collections::HashMap;
-use std::{io::Write, vec};
-use tokio::time::sleep;
+use serde::{Deserialize, Serialize};
+use sink::{open_sink, Sink};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use storage::Storage;
 
+mod archive;
+mod cache;
+mod cli;
+mod clock;
+mod config;
+mod crawler;
+mod drift;
+mod fetch;
+mod frontier;
 mod livescores;
+#[cfg(test)]
+mod mock_server;
+mod profile;
+mod rate_limit;
+mod reparse;
+mod retry;
+mod sink;
+mod storage;
 
-use crate::livescores::LeagueGroupPage;
-
-static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
-pub fn get_client() -> Client {
-    let client = Client::builder()
-        .user_agent(APP_USER_AGENT)
+pub static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+pub fn get_client(config: &Config) -> Client {
+    Client::builder()
+        .user_agent(&config.user_agent)
+        .timeout(Duration::from_millis(config.timeout_ms))
+        .connect_timeout(Duration::from_millis(config.connect_timeout_ms))
         .build()
-        .unwrap();
-    client
+        .unwrap()
 }
 
-#[derive(Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ScraperTask {
     url: String,
     href: String,
     name: String,
+    /// Fetch the page even if it was already visited during this crawl.
+    refresh: bool,
+}
+
+impl ScraperTask {
+    /// Task for the page at the absolute `url`, parsed by the parser `name`.
+    pub fn from_url(url: &str, name: &str) -> Result<ScraperTask, String> {
+        let url = Url::parse(url).map_err(|e| format!("{}: {}", url, e))?;
+        let href = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_owned(),
+        };
+        Ok(ScraperTask {
+            url: url.origin().ascii_serialization(),
+            href,
+            name: name.to_owned(),
+            refresh: false,
+        })
+    }
+
+    pub fn force_refresh(mut self) -> Self {
+        self.refresh = true;
+        self
+    }
+
+    /// Copy of the task whose `href` asks the site to render times in
+    /// `timezone`, in the whole hours the site takes.
+    pub fn in_timezone(&self, timezone: FixedOffset) -> ScraperTask {
+        let hours = timezone.local_minus_utc() / 3600;
+        let (path, query) = match self.href.split('#').next() {
+            Some(href) => href.split_once('?').unwrap_or((href, "")),
+            None => ("", ""),
+        };
+        let mut params: Vec<String> = query
+            .split('&')
+            .filter(|param| !param.is_empty() && !param.starts_with("tz="))
+            .map(|param| param.to_owned())
+            .collect();
+        params.push(format!("tz={}", hours));
+        ScraperTask {
+            href: format!("{}?{}", path, params.join("&")),
+            ..self.clone()
+        }
+    }
 }
 
 pub trait ScripingFunction {
@@ -35,101 +116,618 @@ pub trait ScripingFunction {
 }
 
 pub struct Scraper {
-    client: Client,
+    fetcher: Box<dyn Fetcher>,
     parsers: HashMap<String, Box<dyn ScripingFunction>>,
+    /// Timezone the site renders kickoff times in, sent as `tz` with every request.
+    timezone: FixedOffset,
+    health: HealthChecks,
+    /// Where every fetched page is kept, if anywhere.
+    archive: Option<Archive>,
+}
+
+/// What a parser extracted from a page.
+#[derive(Serialize, Deserialize, Debug)]
+pub enum TaskData {
+    /// The page only links to other pages.
+    Empty,
+    LeagueGroup(LeagueGroupPage),
+    Games(GamesPage),
+    MatchDetail(MatchDetailPage),
 }
 
 #[derive(Debug)]
 pub struct ScraperTaskResult {
     url: String,
-    data: String,
+    data: TaskData,
     success: bool,
     last_update: DateTime<chrono::Utc>,
     new_urls: Vec<ScraperTask>,
+    /// Problems with parts of the page that didn't stop it from being parsed.
+    warnings: Vec<TaskError>,
+    /// Selectors that found the page's fields.
+    fallbacks: FallbackUsage,
+    /// Set when the page parsed but failed the health checks.
+    suspicious: Option<Suspicion>,
+    /// The server answered that the page is unchanged, so its data was
+    /// already stored and written the last time it was fetched.
+    unchanged: bool,
 }
 
 #[derive(Debug)]
 pub enum TaskError {
-    Other(String),
+    Fetch(FetchError),
     Parsing(Html),
     Fragment(String, String),
+    /// Too many rows of the page failed, with the error of each.
+    Rows(Vec<TaskError>),
     NoParsingFunction(String),
 }
 
 impl Scraper {
     async fn scripe(&self, task: &ScraperTask) -> Result<ScraperTaskResult, TaskError> {
+        let task = &task.in_timezone(self.timezone);
         let url = format!("{}{}", &task.url, &task.href);
-        let result = self.client.get(&url).send().await;
-        match result {
-            Ok(response) => match response.status() {
-                StatusCode::OK => {
-                    let raw_html = response.text().await.unwrap();
-                    let document = Html::parse_document(&raw_html);
-                    if let Some(parse) = self.parsers.get(&task.name) {
-                        parse.parse(task, &document)
-                    } else {
-                        Err(TaskError::NoParsingFunction(format!(
-                            "No parsing function {}",
-                            &task.name
-                        )))
+        match self.fetcher.fetch(&Request::get(&url)).await {
+            Ok(response) => {
+                let unchanged = response.status == NOT_MODIFIED;
+                // An unchanged page was archived when it last changed.
+                if let (Some(archive), false) = (&self.archive, unchanged) {
+                    if let Err(e) = archive.store(task, &url, response.status, &response.body) {
+                        println!("Archive Error {}", e);
                     }
                 }
-                _ => Err(TaskError::Other("Something went wrong".to_owned())),
-            },
-            Err(e) => Err(TaskError::Other(e.to_string())),
+                // An unchanged page comes with the body cached from before,
+                // parsed again for the links it leads to.
+                if response.status == StatusCode::OK.as_u16() || unchanged {
+                    let mut result = self.parse(task, &Html::parse_document(&response.body))?;
+                    result.unchanged = unchanged;
+                    Ok(result)
+                } else {
+                    Err(TaskError::Fetch(FetchError::Status {
+                        url,
+                        status: response.status,
+                        retry_after: response
+                            .retry_after
+                            .as_deref()
+                            .and_then(|value| parse_retry_after(value, clock::now())),
+                    }))
+                }
+            }
+            Err(e) => Err(TaskError::Fetch(e)),
+        }
+    }
+
+    fn parse(&self, task: &ScraperTask, document: &Html) -> Result<ScraperTaskResult, TaskError> {
+        match self.parsers.get(&task.name) {
+            Some(parse) => {
+                let mut result = parse.parse(task, document)?;
+                let issues = self.health.inspect(task, &result);
+                if !issues.is_empty() {
+                    result.suspicious = Some(Suspicion {
+                        issues,
+                        html: document.root_element().html(),
+                    });
+                }
+                Ok(result)
+            }
+            None => Err(TaskError::NoParsingFunction(format!(
+                "No parsing function {}",
+                &task.name
+            ))),
         }
     }
 
     fn add_scriping_function(&mut self, f: Box<dyn ScripingFunction>) {
         self.parsers.insert(f.name().to_owned(), f);
     }
+
+    /// Keeps a copy of every fetched page in the archive, if it is enabled.
+    fn archive_to(mut self, config: &ArchiveConfig) -> Scraper {
+        self.archive = config
+            .enabled
+            .then(|| Archive::open(&config.dir).expect("can't open archive"));
+        self
+    }
 }
 
-#[tokio::main]
-async fn main() {
-    let mut queue: Vec<ScraperTask> = vec![];
-    queue.push(MainPage::new_task("https://www.livescores.com", ""));
+/// Destinations of the extracted data besides the console.
+struct Outputs {
+    storage: Option<Storage>,
+    sink: Option<Box<dyn Sink>>,
+    drift: DriftReport,
+}
+
+impl Outputs {
+    /// Opens the outputs given on the command line, falling back to the
+    /// config's output settings when `defaults` are given. Suspicious pages
+    /// are saved to `drift_dir`.
+    fn open(args: &OutputArgs, defaults: Option<&OutputConfig>, drift_dir: &Path) -> Outputs {
+        let database = args
+            .database
+            .as_deref()
+            .or(defaults.map(|config| config.database.as_path()));
+        let sink = match (&args.sink, &args.output, defaults) {
+            (Some(kind), Some(output), _) => Some((kind, output)),
+            (None, None, Some(config)) => config.sink.as_ref().zip(config.path.as_ref()),
+            _ => None,
+        };
+        Outputs {
+            storage: database.map(|path| Storage::open(path).expect("can't open database")),
+            sink: sink.map(|(kind, output)| open_sink(kind, output).expect("can't open sink")),
+            drift: DriftReport::new(drift_dir),
+        }
+    }
+
+    fn report(&mut self, task: &ScraperTask, res: Result<ScraperTaskResult, TaskError>) {
+        match res {
+            Ok(result) => {
+                println!("Url  = {}", result.url);
+                println!("Data = {}", serde_json::to_string(&result.data).unwrap());
+                println!("Last update = {}", result.last_update);
+                println!("Success = {}", result.success);
+                for warning in &result.warnings {
+                    println!("Warning {:?}", warning);
+                }
+                for (field, index, selector, count) in result.fallbacks.fallbacks() {
+                    println!(
+                        "Fallback {} #{} `{}` matched {} times",
+                        field, index, selector, count
+                    );
+                }
+                if let Some(suspicion) = &result.suspicious {
+                    for issue in &suspicion.issues {
+                        println!("Suspicious {}", issue);
+                    }
+                    match self.drift.record(task, &result, suspicion) {
+                        Ok(html) => println!("Writting {}", html.display()),
+                        Err(e) => println!("Drift Error {}", e),
+                    }
+                }
+                if result.unchanged {
+                    println!("Unchanged since the last fetch, not written again");
+                    return;
+                }
+                if let Some(sink) = &mut self.sink {
+                    if let Err(e) = sink.write(task, &result.data) {
+                        println!("Sink Error {}", e);
+                    }
+                }
+                if let (Some(storage), TaskData::Games(page)) = (&mut self.storage, &result.data) {
+                    if let Err(e) = storage.upsert_games(&page.games) {
+                        println!("Storage Error {}", e);
+                    }
+                }
+            }
+            Err(TaskError::Parsing(document)) => {
+                let filename = format!("{}.html", task.name);
+                println!("Writting {}", &filename);
+                let content = document.root_element().html();
+                let mut file = std::fs::File::create(&filename).expect("create failed");
+                file.write_all(content.as_bytes()).expect("write failed");
+            }
+            Err(TaskError::Fragment(name, inner_html)) => {
+                println!("Error parsing framgent {}\n{}\n", name, inner_html);
+            }
+            Err(TaskError::Rows(errors)) => {
+                println!("{} rows failed to parse", errors.len());
+                for error in &errors {
+                    println!("Row Error {:?}", error);
+                }
+            }
+            Err(TaskError::NoParsingFunction(taks_name)) => {
+                println!("No parsing function for task {}", taks_name);
+            }
+            Err(TaskError::Fetch(e)) => {
+                println!("Fetch Error {}", e)
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        if let Some(sink) = &mut self.sink {
+            sink.finish().expect("can't finish sink");
+        }
+        if !self.drift.is_empty() {
+            let path = self.drift.write().expect("can't write drift report");
+            println!(
+                "{} pages may no longer match the selector profiles, see {}",
+                self.drift.len(),
+                path.display()
+            );
+        }
+    }
+}
+
+/// Where the pages come from: the network through the HTTP cache, optionally
+/// recorded to a cassette, or a cassette recorded before.
+fn open_fetcher(config: &Config, record: Option<&Path>, replay: Option<&Path>) -> Box<dyn Fetcher> {
+    let exit = |what: &str, e: std::io::Error| -> ! {
+        eprintln!("Can't open {}: {}", what, e);
+        std::process::exit(2);
+    };
+    if let Some(cassette) = replay {
+        return Box::new(Replayer::load(cassette).unwrap_or_else(|e| exit("cassette", e)));
+    }
+    let mut fetcher: Box<dyn Fetcher> = Box::new(HttpFetcher::new(get_client(config)));
+    if config.cache.enabled {
+        fetcher = Box::new(
+            CachingFetcher::open(fetcher, &config.cache.dir).unwrap_or_else(|e| exit("cache", e)),
+        );
+    }
+    if let Some(cassette) = record {
+        fetcher =
+            Box::new(Recorder::open(fetcher, cassette).unwrap_or_else(|e| exit("cassette", e)));
+    }
+    fetcher
+}
+
+fn livescores_scraper(config: &Config, fetcher: Box<dyn Fetcher>) -> Scraper {
+    let profiles = match &config.profiles {
+        Some(path) => match Profiles::load(path) {
+            Ok(profiles) => {
+                println!(
+                    "Selector profiles {} from {}",
+                    profiles.versions().join(", "),
+                    path.display()
+                );
+                Arc::new(profiles)
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(2);
+            }
+        },
+        None => Profiles::builtin(),
+    };
     let mut scraper = Scraper {
-        client: get_client(),
+        fetcher,
         parsers: HashMap::new(),
+        timezone: config.timezone().expect("validated config"),
+        health: config.drift.clone(),
+        archive: None,
     };
-    scraper.add_scriping_function(Box::new(MainPage::default()));
-    scraper.add_scriping_function(Box::new(LeagueGroupPage::default()));
-    scraper.add_scriping_function(Box::new(GamesPage::default()));
+    scraper.add_scriping_function(Box::new(MainParser {
+        country_filter: config
+            .countries
+            .compile("countries")
+            .expect("validated config"),
+        profiles: profiles.clone(),
+    }));
+    scraper.add_scriping_function(Box::new(LeagueGroupParser {
+        league_filter: config.leagues.compile("leagues").expect("validated config"),
+        profiles: profiles.clone(),
+    }));
+    scraper.add_scriping_function(Box::new(GamesParser {
+        max_error_ratio: config.games.max_error_ratio,
+        profiles: profiles.clone(),
+    }));
+    scraper.add_scriping_function(Box::new(MatchDetailParser { profiles }));
+    scraper
+}
 
-    while queue.len() > 0 {
-        if let Some(task) = queue.pop() {
+async fn crawl(config: &Config, scraper: Scraper, mut outputs: Outputs) {
+    let journal = Path::new("frontier.jsonl");
+    let mut queue = Frontier::open(journal).expect("can't open frontier journal");
+    for start_url in &config.start_urls {
+        let start =
+            ScraperTask::from_url(start_url, MainParser::my_name()).expect("invalid start url");
+        queue.push(start.force_refresh());
+    }
+    let settings = CrawlerSettings {
+        concurrency: config.rate_limit.concurrency,
+        politeness_delay: config.rate_limit.delay(),
+        host_delays: config.rate_limit.host_delays(),
+        burst: config.rate_limit.burst,
+        ..Default::default()
+    };
+    let crawler = Crawler::new(scraper, settings);
+    let dead_letters = crawler
+        .run(queue, |task, res| outputs.report(task, res))
+        .await;
+    outputs.finish();
+    // The crawl is complete, the next run starts a fresh one.
+    std::fs::remove_file(journal).expect("remove failed");
+    if !dead_letters.is_empty() {
+        println!(
+            "{} tasks failed, see dead_letters.jsonl",
+            dead_letters.len()
+        );
+        write_dead_letters(Path::new("dead_letters.jsonl"), &dead_letters).expect("write failed");
+    }
+}
+
+/// Runs the archived pages whose URL contains `url` through the current
+/// parsers, as of the time they were fetched, and prints how their games
+/// differ from the ones in the config's database.
+fn reparse(config: &Config, scraper: &Scraper, url: Option<&str>, mut outputs: Outputs) {
+    let archive = Archive::open(&config.archive.dir).expect("can't open archive");
+    let stored = Storage::open(&config.output.database)
+        .and_then(|storage| storage.games())
+        .expect("can't read stored games");
+    // A page fetched again replaces the games of its earlier fetches.
+    let mut parsed = BTreeMap::new();
+    let entries = archive.entries().expect("can't read archive");
+    for entry in entries
+        .iter()
+        .filter(|entry| entry.status == StatusCode::OK.as_u16())
+        .filter(|entry| url.is_none_or(|url| entry.url.contains(url)))
+    {
+        let raw_html = match archive.read(entry) {
+            Ok(raw_html) => raw_html,
+            Err(e) => {
+                println!("Archive Error {}: {}", entry.sha256, e);
+                continue;
+            }
+        };
+        let res = clock::with_time(entry.fetched_at, || {
+            scraper.parse(&entry.task, &Html::parse_document(&raw_html))
+        });
+        if let Ok(ScraperTaskResult {
+            data: TaskData::Games(page),
+            ..
+        }) = &res
+        {
+            for game in &page.games {
+                parsed.insert(storage::natural_key(game), game.clone());
+            }
+        }
+        outputs.report(&entry.task, res);
+    }
+    outputs.finish();
+    let parsed: Vec<_> = parsed.into_values().collect();
+    let changes = diff_games(&stored, &parsed);
+    for change in &changes {
+        match change {
+            GameChange::Added(key) => println!("New {}", key),
+            GameChange::Changed {
+                key,
+                field,
+                stored,
+                parsed,
+            } => println!("Changed {} {}: {} -> {}", key, field, stored, parsed),
+        }
+    }
+    println!(
+        "Parsed {} games, {} changes against {}",
+        parsed.len(),
+        changes.len(),
+        config.output.database.display()
+    );
+}
+
+/// Reads the config given with `--config`, otherwise scraper.toml if it
+/// exists, and exits with the error when it is invalid.
+fn load_config(path: Option<&Path>) -> Config {
+    let default_path = Path::new("scraper.toml");
+    let path = match path {
+        Some(path) => path,
+        None if default_path.exists() => default_path,
+        None => return Config::default(),
+    };
+    match Config::load(path) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    let mut config = load_config(cli.config.as_deref());
+    let (record, replay) = (cli.record.as_deref(), cli.replay.as_deref());
+    // Only the commands that fetch open the cache and the archive, the
+    // others get a fetcher without any page.
+    let offline = || -> Box<dyn Fetcher> { Box::new(Replayer::default()) };
+    match cli.command {
+        Command::Crawl {
+            start_url,
+            concurrency,
+            output,
+        } => {
+            if let Some(start_url) = start_url {
+                config.start_urls = vec![start_url];
+            }
+            if let Some(concurrency) = concurrency {
+                config.rate_limit.concurrency = concurrency;
+            }
+            if let Err(e) = config.validate() {
+                eprintln!("{}", e);
+                std::process::exit(2);
+            }
+            let outputs = Outputs::open(&output, Some(&config.output), &config.drift.dir);
+            let scraper = livescores_scraper(&config, open_fetcher(&config, record, replay))
+                .archive_to(&config.archive);
+            crawl(&config, scraper, outputs).await;
+        }
+        Command::Fetch {
+            url,
+            parser,
+            output,
+        } => {
+            let mut outputs = Outputs::open(&output, None, &config.drift.dir);
+            let task = ScraperTask::from_url(&url, &parser).expect("invalid url");
+            let scraper = livescores_scraper(&config, open_fetcher(&config, record, replay))
+                .archive_to(&config.archive);
             let res = scraper.scripe(&task).await;
-            match res {
-                Ok(mut result) => {
-                    println!("Url  = {}", result.url);
-                    println!("Data = {}", result.data);
-                    println!("Last update = {}", result.last_update);
-                    println!("Success = {}", result.success);
-                    if result.success {
-                        queue.append(&mut result.new_urls);
-                    } else {
-                        // redo later
+            outputs.report(&task, res);
+            outputs.finish();
+        }
+        Command::ParseFile {
+            path,
+            parser,
+            url,
+            output,
+        } => {
+            let mut outputs = Outputs::open(&output, None, &config.drift.dir);
+            let task = ScraperTask::from_url(&url, &parser).expect("invalid url");
+            let raw_html = std::fs::read_to_string(&path).expect("can't read file");
+            let res = livescores_scraper(&config, offline())
+                .parse(&task, &Html::parse_document(&raw_html));
+            outputs.report(&task, res);
+            outputs.finish();
+        }
+        Command::Reparse { url, output } => {
+            let outputs = Outputs::open(&output, None, &config.drift.dir);
+            let scraper = livescores_scraper(&config, offline());
+            reparse(&config, &scraper, url.as_deref(), outputs);
+        }
+        Command::Archive { url, body } => {
+            let archive = Archive::open(&config.archive.dir).expect("can't open archive");
+            let entries = archive.entries().expect("can't read archive");
+            let matching: Vec<_> = entries
+                .iter()
+                .filter(|entry| url.as_ref().is_none_or(|url| entry.url.contains(url)))
+                .collect();
+            if body {
+                match matching.last() {
+                    Some(entry) => print!("{}", archive.read(entry).expect("can't read page")),
+                    None => {
+                        eprintln!("No archived page");
+                        std::process::exit(1);
                     }
                 }
-                Err(TaskError::Parsing(document)) => {
-                    let filename = format!("{}.html", task.name);
-                    println!("Writting {}", &filename);
-                    let content = document.root_element().html();
-                    let mut file = std::fs::File::create(&filename).expect("create failed");
-                    file.write_all(content.as_bytes()).expect("write failed");
-                }
-                Err(TaskError::Fragment(name, inner_html)) => {
-                    println!("Error parsing framgent {}\n{}\n", name, inner_html);
-                }
-                Err(TaskError::NoParsingFunction(taks_name)) => {
-                    println!("No parsing function for task {}", taks_name);
-                }
-                Err(TaskError::Other(message)) => {
-                    println!("Other Error {}", message)
+            } else {
+                for entry in matching {
+                    println!(
+                        "{} {} {} {} {}",
+                        entry.fetched_at, entry.status, entry.task.name, entry.sha256, entry.url
+                    );
                 }
+            }
+        }
+        Command::Export {
+            sink,
+            output,
+            database,
+        } => {
+            let database = database.unwrap_or(config.output.database);
+            let storage = Storage::open(&database).expect("can't open database");
+            let games = storage.games().expect("can't read games");
+            println!("Exporting {} games", games.len());
+            let task = ScraperTask {
+                url: format!("sqlite:{}", database.display()),
+                href: "".to_owned(),
+                name: GamesParser::my_name().to_owned(),
+                refresh: false,
+            };
+            let data = TaskData::Games(GamesPage { games });
+            let mut sink = open_sink(&sink, &output).expect("can't open sink");
+            sink.write(&task, &data).expect("export failed");
+            sink.finish().expect("export failed");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::FixedOffset;
+    use clap::{CommandFactory, Parser};
+    use futures::future::BoxFuture;
+
+    use crate::cache::{CachingFetcher, NOT_MODIFIED};
+    use crate::cli::{Cli, Command, OutputArgs};
+    use crate::config::Config;
+    use crate::fetch::{FetchError, Fetcher, Request, Response};
+    use crate::livescores::GamesParser;
+    use crate::{livescores_scraper, Outputs, ScraperTask, ScripingFunction};
+
+    /// Serves a games page tagged "v1" and honours `If-None-Match`.
+    struct Origin;
+
+    impl Fetcher for Origin {
+        fn fetch<'a>(
+            &'a self,
+            request: &'a Request,
+        ) -> BoxFuture<'a, Result<Response, FetchError>> {
+            let response = match request.if_none_match.as_deref() {
+                Some("\"v1\"") => Response::new(NOT_MODIFIED, ""),
+                _ => Response {
+                    etag: Some("\"v1\"".to_owned()),
+                    ..Response::new(
+                        200,
+                        r#"<div class="db"><div class="yf"><span class="cb">December 10, 2020</span></div><div class="yf"><a class="qd" href="/football/europa-league-20-21/"><span class="eb">Europa League 20/21</span></a></div><div class="yf"><a href="/football/europa-league-20-21/group-g-2020-2021/leicester-city-vs-aek-athens/316190/" class="qd"><span class="Pg Lg">FT</span><span class="eh">Leicester City</span><span class="hh">2</span><span class="ih">0</span><span class="eh">AEK Athens</span></a></div></div>"#,
+                    )
+                },
             };
-            sleep(std::time::Duration::from_secs(1)).await;
+            Box::pin(async move { Ok(response) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unchanged_page_is_not_written_again() {
+        let dir = std::env::temp_dir().join(format!("unchanged-{}", std::process::id()));
+        let config = Config {
+            ..Default::default()
+        };
+        let fetcher = CachingFetcher::open(Origin, &dir.join("cache")).unwrap();
+        let scraper = livescores_scraper(&config, Box::new(fetcher));
+        let output = dir.join("games.jsonl");
+        let args = OutputArgs {
+            sink: Some("jsonl".to_owned()),
+            output: Some(output.clone()),
+            database: None,
+        };
+        let mut outputs = Outputs::open(&args, None, &dir.join("drift"));
+        let task = GamesParser::new_task("https://www.livescores.com", "/football/europa-league/");
+        for _ in 0..2 {
+            let result = scraper.scripe(&task).await;
+            // The links of an unchanged page are still followed.
+            assert_eq!(result.as_ref().unwrap().new_urls.len(), 1);
+            outputs.report(&task, result);
         }
+        outputs.finish();
+        let written = std::fs::read_to_string(&output).unwrap();
+        assert_eq!(written.lines().count(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_task_in_timezone() {
+        let task =
+            GamesParser::new_task("https://www.livescores.com", "/football/england/?tz=3#top");
+        let utc = FixedOffset::east_opt(0).unwrap();
+        assert_eq!(task.in_timezone(utc).href, "/football/england/?tz=0");
+        let task = GamesParser::new_task("https://www.livescores.com", "/?date=20230128");
+        let brazil = FixedOffset::west_opt(3 * 3600).unwrap();
+        assert_eq!(task.in_timezone(brazil).href, "/?date=20230128&tz=-3");
+    }
+
+    #[test]
+    fn test_task_from_url() {
+        let task = ScraperTask::from_url(
+            "https://www.livescores.com/football/spain/laliga/?date=20230128",
+            "games",
+        )
+        .unwrap();
+        assert_eq!(task.url, "https://www.livescores.com");
+        assert_eq!(task.href, "/football/spain/laliga/?date=20230128");
+        assert_eq!(task.name, "games");
+        assert!(ScraperTask::from_url("livescores.com", "games").is_err());
+    }
+
+    #[test]
+    fn test_cli() {
+        Cli::command().debug_assert();
+        let cli = Cli::try_parse_from([
+            "scraper",
+            "fetch",
+            "https://www.livescores.com/football/spain/",
+            "--parser",
+            "games",
+            "--sink",
+            "csv",
+            "--output",
+            "spain.csv",
+        ])
+        .unwrap();
+        let Command::Fetch { parser, output, .. } = cli.command else {
+            panic!("expected fetch");
+        };
+        assert_eq!(parser, "games");
+        assert_eq!(output.sink.as_deref(), Some("csv"));
+        assert!(Cli::try_parse_from(["scraper", "crawl", "--sink", "csv"]).is_err());
     }
 }
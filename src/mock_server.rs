@@ -0,0 +1,331 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// How the server answers a request.
+#[derive(Clone, Debug)]
+pub enum Reply {
+    Page {
+        status: u16,
+        body: String,
+    },
+    /// `reply`, after waiting `delay`.
+    Slow {
+        delay: Duration,
+        reply: Box<Reply>,
+    },
+    /// 429 Too Many Requests asking to wait `retry_after` seconds.
+    Throttle {
+        retry_after: u64,
+    },
+    /// Keeps the connection open without ever answering.
+    Hang,
+}
+
+impl Reply {
+    pub fn ok(body: &str) -> Reply {
+        Reply::Page {
+            status: 200,
+            body: body.to_owned(),
+        }
+    }
+
+    pub fn fixture(path: &str) -> Reply {
+        Reply::ok(&std::fs::read_to_string(path).expect("fixture"))
+    }
+
+    pub fn status(status: u16) -> Reply {
+        Reply::Page {
+            status,
+            body: String::new(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Routes {
+    /// Replies to a path and query, given in turn, the last one repeated.
+    replies: HashMap<String, VecDeque<Reply>>,
+    hits: Vec<String>,
+}
+
+/// HTTP server on a local port standing in for livescores.com. Paths without
+/// a route get 404.
+pub struct MockServer {
+    addr: SocketAddr,
+    routes: Arc<Mutex<Routes>>,
+}
+
+impl MockServer {
+    /// Starts serving on the current tokio runtime.
+    pub async fn start() -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let addr = listener.local_addr().expect("local address");
+        let routes = Arc::new(Mutex::new(Routes::default()));
+        let server_routes = routes.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(answer(stream, server_routes.clone()));
+            }
+        });
+        MockServer { addr, routes }
+    }
+
+    /// Origin of the server, like "http://127.0.0.1:40123".
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Answers the requests for `path`, which includes the query, with
+    /// `replies` in turn and then keeps repeating the last one.
+    pub fn route(&self, path: &str, replies: Vec<Reply>) {
+        assert!(!replies.is_empty(), "no reply for {}", path);
+        let mut routes = self.routes.lock().unwrap();
+        routes.replies.insert(path.to_owned(), replies.into());
+    }
+
+    /// Number of requests for `path` so far.
+    pub fn hits(&self, path: &str) -> usize {
+        let routes = self.routes.lock().unwrap();
+        routes.hits.iter().filter(|hit| *hit == path).count()
+    }
+}
+
+async fn answer(mut stream: TcpStream, routes: Arc<Mutex<Routes>>) {
+    let mut request = vec![];
+    let mut buffer = [0; 4096];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        match stream.read(&mut buffer).await {
+            Ok(0) | Err(_) => return,
+            Ok(read) => request.extend_from_slice(&buffer[..read]),
+        }
+    }
+    // "GET /football/england/?tz=0 HTTP/1.1"
+    let request = String::from_utf8_lossy(&request);
+    let path = request.split(' ').nth(1).unwrap_or("/").to_owned();
+    let mut reply = {
+        let mut routes = routes.lock().unwrap();
+        routes.hits.push(path.clone());
+        match routes.replies.get_mut(&path) {
+            Some(replies) if replies.len() > 1 => replies.pop_front().unwrap(),
+            Some(replies) => replies[0].clone(),
+            None => Reply::status(404),
+        }
+    };
+    let mut headers = String::new();
+    loop {
+        match reply {
+            Reply::Throttle { retry_after } => {
+                headers = format!("Retry-After: {}\r\n", retry_after);
+                reply = Reply::status(429);
+            }
+            Reply::Page { status, body } => {
+                let reason = StatusCode::from_u16(status)
+                    .ok()
+                    .and_then(|status| status.canonical_reason())
+                    .unwrap_or("");
+                let response = format!(
+                    "HTTP/1.1 {} {}\r\n{}Content-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    reason,
+                    headers,
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+                let _ = stream.shutdown().await;
+                return;
+            }
+            Reply::Slow { delay, reply: next } => {
+                tokio::time::sleep(delay).await;
+                reply = *next;
+            }
+            Reply::Hang => {
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use chrono::{TimeZone, Utc};
+
+    use crate::config::{Config, Patterns};
+    use crate::crawler::{Crawler, CrawlerSettings};
+    use crate::fetch::HttpFetcher;
+    use crate::frontier::Frontier;
+    use crate::livescores::{GamesParser, MainParser};
+    use crate::mock_server::{MockServer, Reply};
+    use crate::retry::RetryPolicy;
+    use crate::{get_client, livescores_scraper, ScraperTask, ScripingFunction, TaskData};
+
+    const GAMES: &str =
+        "/football/womens-world-cup-qualification/inter-confederation-qualification-play-off/?tz=0";
+    const LEICESTER: &str =
+        "/football/europa-league-20-21/group-g-2020-2021/leicester-city-vs-aek-athens/316190/?tz=0";
+    const BRAGA: &str =
+        "/football/europa-league-20-21/group-g-2020-2021/sc-braga-vs-zorya/316191/?tz=0";
+
+    fn england_only() -> Config {
+        Config {
+            countries: Patterns {
+                include: vec!["^England$".to_owned()],
+                exclude: vec![],
+            },
+            leagues: Patterns {
+                include: vec!["^Inter-Confederation".to_owned()],
+                exclude: vec![],
+            },
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_crawl_waits_for_retry_after() {
+        let server = MockServer::start().await;
+        server.route(
+            "/?tz=0",
+            vec![
+                Reply::Throttle { retry_after: 1 },
+                Reply::fixture("test_data/main_page_2024_04_28.html"),
+            ],
+        );
+        let scraper = livescores_scraper(
+            &england_only(),
+            Box::new(HttpFetcher::new(get_client(&england_only()))),
+        );
+        let settings = CrawlerSettings {
+            politeness_delay: Duration::ZERO,
+            retry: RetryPolicy {
+                max_attempts: 2,
+                base_delay: Duration::from_millis(10),
+                max_delay: Duration::from_millis(10),
+            },
+            ..Default::default()
+        };
+        let mut queue = Frontier::default();
+        queue.push(ScraperTask::from_url(&server.url(), MainParser::my_name()).unwrap());
+
+        let started = Instant::now();
+        Crawler::new(scraper, settings).run(queue, |_, _| {}).await;
+        assert_eq!(server.hits("/?tz=0"), 2);
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_crawl_against_mock_server() {
+        let server = MockServer::start().await;
+        server.route(
+            "/?tz=0",
+            vec![Reply::fixture("test_data/main_page_2024_04_28.html")],
+        );
+        // Fails once, then recovers.
+        server.route(
+            "/football/england/?tz=0",
+            vec![
+                Reply::status(500),
+                Reply::fixture("test_data/parse_country.html"),
+            ],
+        );
+        server.route(
+            GAMES,
+            vec![Reply::Slow {
+                delay: Duration::from_millis(50),
+                reply: Box::new(Reply::fixture("test_data/games_2023_01_28.html")),
+            }],
+        );
+        // No route for the Leicester game, it gets 404.
+        server.route(BRAGA, vec![Reply::Hang]);
+
+        let config = Config {
+            timeout_ms: 200,
+            ..england_only()
+        };
+        let scraper = livescores_scraper(&config, Box::new(HttpFetcher::new(get_client(&config))));
+        let settings = CrawlerSettings {
+            politeness_delay: Duration::ZERO,
+            retry: RetryPolicy {
+                max_attempts: 2,
+                base_delay: Duration::from_millis(10),
+                max_delay: Duration::from_millis(10),
+            },
+            ..Default::default()
+        };
+        let mut queue = Frontier::default();
+        queue.push(ScraperTask::from_url(&server.url(), MainParser::my_name()).unwrap());
+
+        let mut parsed = vec![];
+        let dead_letters = Crawler::new(scraper, settings)
+            .run(queue, |task, res| {
+                if res.is_ok_and(|result| result.success) {
+                    parsed.push(task.name.clone());
+                }
+            })
+            .await;
+        assert_eq!(parsed, ["main", "league_group", "games"]);
+        assert_eq!(server.hits("/football/england/?tz=0"), 2);
+        let mut failed: Vec<(String, u32, &str)> = dead_letters
+            .iter()
+            .map(|letter| {
+                let href = format!("{}?tz=0", letter.task.href);
+                let error = letter.error.split(' ').next().unwrap_or_default();
+                (href, letter.attempts, error)
+            })
+            .collect();
+        failed.sort();
+        // A missing page isn't fetched again, a hanging one is.
+        assert_eq!(
+            failed,
+            [
+                (LEICESTER.to_owned(), 1, "HTTP"),
+                (BRAGA.to_owned(), 2, "Timed")
+            ]
+        );
+        assert_eq!(server.hits(LEICESTER), 1);
+    }
+
+    #[tokio::test]
+    async fn test_crawl_in_site_timezone() {
+        let server = MockServer::start().await;
+        server.route(
+            "/football/england/?tz=3",
+            vec![Reply::ok(
+                r#"<div class="db"><div class="yf"><span class="cb">April 28, 2024</span></div><div class="yf"><a class="qd" href="/football/england/premier-league/"><span class="eb">Premier League</span></a></div><div class="yf"><a href="/football/england/premier-league/tottenham-hotspur-vs-arsenal/968096/" class="qd"><span class="Pg Lg">16:00</span><span class="eh">Tottenham Hotspur</span><span class="hh">?</span><span class="ih">?</span><span class="eh">Arsenal</span></a></div></div>"#,
+            )],
+        );
+        let config = Config {
+            timezone: "+03:00".to_owned(),
+            ..england_only()
+        };
+        config.validate().unwrap();
+        let scraper = livescores_scraper(&config, Box::new(HttpFetcher::new(get_client(&config))));
+        let settings = CrawlerSettings {
+            politeness_delay: Duration::ZERO,
+            ..Default::default()
+        };
+        let mut queue = Frontier::default();
+        queue.push(GamesParser::new_task(&server.url(), "/football/england/"));
+
+        let mut kickoffs = vec![];
+        Crawler::new(scraper, settings)
+            .run(queue, |_, res| {
+                if let Ok(TaskData::Games(page)) = res.map(|result| result.data) {
+                    kickoffs.extend(page.games.iter().map(|game| game.kickoff));
+                }
+            })
+            .await;
+        assert_eq!(server.hits("/football/england/?tz=3"), 1);
+        assert_eq!(
+            kickoffs,
+            [Some(Utc.with_ymd_and_hms(2024, 4, 28, 13, 0, 0).unwrap())]
+        );
+    }
+}
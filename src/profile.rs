@@ -0,0 +1,359 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+use scraper::{ElementRef, Html, Selector};
+use serde::Deserialize;
+
+/// Profiles built into the binary, used unless the config names a file.
+const BUILTIN: &str = include_str!("../profiles/livescores.toml");
+
+/// Selectors of one layout of the site.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub version: String,
+    pub main: MainSelectors,
+    pub league_group: LeagueGroupSelectors,
+    pub games: GamesSelectors,
+    pub match_detail: MatchDetailSelectors,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct MainSelectors {
+    /// Menu links to the countries.
+    pub country: Chain,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct LeagueGroupSelectors {
+    /// Menu links to the leagues of the country.
+    pub league: Chain,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct GamesSelectors {
+    /// Blocks holding either the date and league headers or the games.
+    pub section: Chain,
+    pub date: Chain,
+    /// Links inside a section, either a league header or a game.
+    pub row: Chain,
+    pub league: Chain,
+    pub stage: Chain,
+    pub status: Chain,
+    pub home_team: Chain,
+    pub away_team: Chain,
+    pub home_score: Chain,
+    pub away_score: Chain,
+    /// Element whose `data-favouritesdetails` attribute ends with the
+    /// kickoff in milliseconds since the epoch.
+    pub kickoff: Chain,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct MatchDetailSelectors {
+    pub incident: Chain,
+    pub icon: Chain,
+    pub time: Chain,
+    pub home_player: Chain,
+    pub away_player: Chain,
+    pub other_player: Chain,
+}
+
+/// A CSS selector checked when the profile is loaded.
+#[derive(Debug)]
+pub struct Css {
+    source: String,
+    selector: Selector,
+}
+
+impl TryFrom<String> for Css {
+    type Error = String;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        let selector = Selector::parse(&source)
+            .map_err(|e| format!("invalid selector {:?}: {:?}", source, e.kind))?;
+        Ok(Css { source, selector })
+    }
+}
+
+impl Deref for Css {
+    type Target = Selector;
+
+    fn deref(&self) -> &Selector {
+        &self.selector
+    }
+}
+
+/// The `nth` element, counting from 0, matched by `css`.
+#[derive(Debug)]
+pub struct Fallback {
+    pub css: Css,
+    pub nth: usize,
+}
+
+impl fmt::Display for Fallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.nth {
+            0 => f.write_str(&self.css.source),
+            nth => write!(f, "{} #{}", self.css.source, nth),
+        }
+    }
+}
+
+/// Selectors tried in order until one matches, so a field can be found by a
+/// stable `data-testid` first and by the generated class names otherwise.
+///
+/// Written as a single selector, a list of selectors, or a list mixing
+/// selectors and `{ css = "...", nth = 1 }` tables.
+#[derive(Deserialize, Debug)]
+#[serde(try_from = "ChainDef")]
+pub struct Chain(Vec<Fallback>);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ChainDef {
+    One(FallbackDef),
+    Many(Vec<FallbackDef>),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FallbackDef {
+    Css(String),
+    Nth { css: String, nth: usize },
+}
+
+impl TryFrom<ChainDef> for Chain {
+    type Error = String;
+
+    fn try_from(def: ChainDef) -> Result<Self, Self::Error> {
+        let defs = match def {
+            ChainDef::One(def) => vec![def],
+            ChainDef::Many(defs) => defs,
+        };
+        if defs.is_empty() {
+            return Err("empty selector list".to_owned());
+        }
+        defs.into_iter()
+            .map(|def| match def {
+                FallbackDef::Css(css) => Ok(Fallback {
+                    css: Css::try_from(css)?,
+                    nth: 0,
+                }),
+                FallbackDef::Nth { css, nth } => Ok(Fallback {
+                    css: Css::try_from(css)?,
+                    nth,
+                }),
+            })
+            .collect::<Result<Vec<Fallback>, String>>()
+            .map(Chain)
+    }
+}
+
+impl Chain {
+    /// Element matched by the first fallback that matches anything in
+    /// `scope`; `field` names the chain in `usage`.
+    pub fn first<'a>(
+        &self,
+        field: &'static str,
+        scope: ElementRef<'a>,
+        usage: &mut FallbackUsage,
+    ) -> Option<ElementRef<'a>> {
+        self.0.iter().enumerate().find_map(|(index, fallback)| {
+            let element = scope.select(&fallback.css).nth(fallback.nth)?;
+            usage.record(field, index, fallback, 1);
+            Some(element)
+        })
+    }
+
+    /// Every element matched by the first fallback that matches anything.
+    pub fn all<'a>(
+        &self,
+        field: &'static str,
+        scope: ElementRef<'a>,
+        usage: &mut FallbackUsage,
+    ) -> Vec<ElementRef<'a>> {
+        for (index, fallback) in self.0.iter().enumerate() {
+            let elements: Vec<ElementRef> =
+                scope.select(&fallback.css).skip(fallback.nth).collect();
+            if !elements.is_empty() {
+                usage.record(field, index, fallback, elements.len());
+                return elements;
+            }
+        }
+        vec![]
+    }
+
+    fn matches(&self, document: &Html) -> bool {
+        self.0
+            .iter()
+            .any(|fallback| document.select(&fallback.css).nth(fallback.nth).is_some())
+    }
+}
+
+/// Which fallback of each chain matched while parsing a page, and how many
+/// elements it found.
+#[derive(Default, Debug, PartialEq)]
+pub struct FallbackUsage {
+    matches: BTreeMap<(&'static str, usize), (String, usize)>,
+}
+
+impl FallbackUsage {
+    fn record(&mut self, field: &'static str, index: usize, fallback: &Fallback, found: usize) {
+        self.matches
+            .entry((field, index))
+            .or_insert_with(|| (fallback.to_string(), 0))
+            .1 += found;
+    }
+
+    /// Number of times the `index`-th fallback of `field` matched.
+    #[cfg(test)]
+    pub fn count(&self, field: &str, index: usize) -> usize {
+        self.matches
+            .iter()
+            .find(|((name, i), _)| *name == field && *i == index)
+            .map_or(0, |(_, (_, count))| *count)
+    }
+
+    /// Number of elements found for `field` by any of its fallbacks.
+    pub fn matched(&self, field: &str) -> usize {
+        self.matches
+            .iter()
+            .filter(|((name, _), _)| *name == field)
+            .map(|(_, (_, count))| count)
+            .sum()
+    }
+
+    /// Field, position in its chain, selector and count of the fallbacks
+    /// that matched although an earlier selector was preferred.
+    pub fn fallbacks(&self) -> impl Iterator<Item = (&str, usize, &str, usize)> {
+        self.matches
+            .iter()
+            .filter(|((_, index), _)| *index > 0)
+            .map(|((field, index), (selector, count))| (*field, *index, selector.as_str(), *count))
+    }
+}
+
+/// Profiles in the order they are tried, newest layout first.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Profiles {
+    #[serde(rename = "profile")]
+    profiles: Vec<Profile>,
+}
+
+#[derive(Debug)]
+pub enum ProfileError {
+    Read(PathBuf, std::io::Error),
+    Syntax(String, toml::de::Error),
+    Empty(String),
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProfileError::Read(path, e) => write!(f, "Can't read {}: {}", path.display(), e),
+            ProfileError::Syntax(source, e) => {
+                write!(f, "Invalid selector profiles {}: {}", source, e)
+            }
+            ProfileError::Empty(source) => write!(f, "No selector profile in {}", source),
+        }
+    }
+}
+
+impl std::error::Error for ProfileError {}
+
+impl Profiles {
+    pub fn load(path: &Path) -> Result<Profiles, ProfileError> {
+        let content =
+            std::fs::read_to_string(path).map_err(|e| ProfileError::Read(path.to_owned(), e))?;
+        Self::parse(&content, &path.display().to_string())
+    }
+
+    fn parse(content: &str, source: &str) -> Result<Profiles, ProfileError> {
+        let profiles: Profiles =
+            toml::from_str(content).map_err(|e| ProfileError::Syntax(source.to_owned(), e))?;
+        if profiles.profiles.is_empty() {
+            return Err(ProfileError::Empty(source.to_owned()));
+        }
+        Ok(profiles)
+    }
+
+    /// The profiles shipped with the binary.
+    pub fn builtin() -> Arc<Profiles> {
+        static PROFILES: OnceLock<Arc<Profiles>> = OnceLock::new();
+        PROFILES
+            .get_or_init(|| {
+                Arc::new(
+                    Self::parse(BUILTIN, "profiles/livescores.toml").expect("builtin profiles"),
+                )
+            })
+            .clone()
+    }
+
+    /// First profile whose `probe` selector matches something in `document`,
+    /// or the newest one when none does.
+    pub fn pick<F>(&self, document: &Html, probe: F) -> &Profile
+    where
+        F: Fn(&Profile) -> &Chain,
+    {
+        self.profiles
+            .iter()
+            .find(|profile| probe(profile).matches(document))
+            .unwrap_or(&self.profiles[0])
+    }
+
+    /// Versions of the profiles, newest first.
+    pub fn versions(&self) -> Vec<&str> {
+        self.profiles
+            .iter()
+            .map(|profile| profile.version.as_str())
+            .collect()
+    }
+
+    #[cfg(test)]
+    pub fn newest(&self) -> &Profile {
+        &self.profiles[0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use scraper::Html;
+
+    use crate::profile::{ProfileError, Profiles};
+
+    #[test]
+    fn test_pick_profile_matching_page() {
+        let profiles = Profiles::builtin();
+        assert_eq!(profiles.newest().version, "2024-04");
+        let content = std::fs::read_to_string("test_data/parse_country.html").unwrap();
+        let document = Html::parse_document(&content);
+        assert_eq!(
+            profiles.pick(&document, |p| &p.games.section).version,
+            "2023-01"
+        );
+        let content = std::fs::read_to_string("test_data/main_page_2024_04_28.html").unwrap();
+        let document = Html::parse_document(&content);
+        assert_eq!(
+            profiles.pick(&document, |p| &p.main.country).version,
+            "2024-04"
+        );
+    }
+
+    #[test]
+    fn test_invalid_selector_is_reported() {
+        let content = include_str!("../profiles/livescores.toml").replace("span.cb", "span..cb");
+        let error = Profiles::parse(&content, "profiles.toml").unwrap_err();
+        assert!(matches!(error, ProfileError::Syntax(..)));
+        assert!(error.to_string().contains("span..cb"), "{}", error);
+        assert!(Profiles::parse("", "profiles.toml").is_err());
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use tokio::time::Instant;
+
+/// Interval a host is slowed down to, at least, the first time it throttles.
+const MIN_THROTTLED_INTERVAL: Duration = Duration::from_millis(500);
+/// Slowest pace a throttling host is slowed down to.
+const MAX_INTERVAL: Duration = Duration::from_secs(60);
+/// Longest `Retry-After` waited for. A server asking for more is tried again
+/// after this, and throttles us again if it still needs to.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(600);
+
+/// Token bucket of a host. The bucket holds up to `burst` requests and
+/// refills one every `interval`; a token taken from an empty bucket is owed
+/// and pushes the following requests back.
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+    /// Current pace, `base_interval` unless the host asked us to slow down.
+    interval: Duration,
+    base_interval: Duration,
+    /// Set by a `Retry-After`, no request starts before it.
+    blocked_until: Option<Instant>,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant, burst: f64) {
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        self.refilled_at = self.refilled_at.max(now);
+        self.tokens = if self.interval.is_zero() {
+            burst
+        } else {
+            (self.tokens + elapsed.as_secs_f64() / self.interval.as_secs_f64()).min(burst)
+        };
+    }
+}
+
+/// Paces the requests to each host, slowing down when a host answers 429 or
+/// 503 and speeding back up with every success.
+pub struct RateLimiter {
+    default_interval: Duration,
+    host_intervals: HashMap<String, Duration>,
+    burst: f64,
+    buckets: HashMap<String, Bucket>,
+}
+
+impl RateLimiter {
+    /// Allows `burst` requests at once to a host and then one every
+    /// `default_interval`, or the host's entry in `host_intervals`.
+    pub fn new(
+        default_interval: Duration,
+        host_intervals: HashMap<String, Duration>,
+        burst: u32,
+    ) -> Self {
+        RateLimiter {
+            default_interval,
+            host_intervals,
+            burst: burst.max(1) as f64,
+            buckets: HashMap::new(),
+        }
+    }
+
+    fn bucket(&mut self, host: &str, now: Instant) -> &mut Bucket {
+        let interval = self
+            .host_intervals
+            .get(host)
+            .copied()
+            .unwrap_or(self.default_interval);
+        let burst = self.burst;
+        self.buckets.entry(host.to_owned()).or_insert(Bucket {
+            tokens: burst,
+            refilled_at: now,
+            interval,
+            base_interval: interval,
+            blocked_until: None,
+        })
+    }
+
+    /// When a request to `host` may start without owing a token.
+    pub fn ready_at(&mut self, host: &str, now: Instant) -> Instant {
+        let burst = self.burst;
+        let bucket = self.bucket(host, now);
+        let now = now.max(bucket.blocked_until.unwrap_or(now));
+        bucket.refill(now, burst);
+        if bucket.tokens >= 1.0 {
+            now
+        } else {
+            now + bucket.interval.mul_f64(1.0 - bucket.tokens)
+        }
+    }
+
+    /// Takes a token for a request to `host` and returns when it may start.
+    pub fn reserve(&mut self, host: &str, now: Instant) -> Instant {
+        let burst = self.burst;
+        let bucket = self.bucket(host, now);
+        let now = now.max(bucket.blocked_until.unwrap_or(now));
+        bucket.refill(now, burst);
+        bucket.tokens -= 1.0;
+        if bucket.tokens >= 0.0 {
+            now
+        } else {
+            now + bucket.interval.mul_f64(-bucket.tokens)
+        }
+    }
+
+    /// The host answered 429 or 503: halve the pace and, when the server said
+    /// how long to wait, start nothing before that.
+    pub fn throttled(&mut self, host: &str, retry_after: Option<Duration>, now: Instant) {
+        let bucket = self.bucket(host, now);
+        bucket.interval = (bucket.interval * 2).clamp(MIN_THROTTLED_INTERVAL, MAX_INTERVAL);
+        bucket.tokens = bucket.tokens.min(0.0);
+        if let Some(wait) = retry_after {
+            let until = now + wait.min(MAX_RETRY_AFTER);
+            bucket.blocked_until = Some(bucket.blocked_until.map_or(until, |at| at.max(until)));
+        }
+    }
+
+    /// A request to `host` went through: move the pace a tenth of the way
+    /// back to the configured one.
+    pub fn succeeded(&mut self, host: &str, now: Instant) {
+        let bucket = self.bucket(host, now);
+        if bucket.interval > bucket.base_interval {
+            let excess = bucket.interval - bucket.base_interval;
+            bucket.interval = bucket.base_interval + excess.mul_f64(0.9);
+        }
+    }
+
+    /// Current pace of requests to `host`.
+    pub fn interval(&self, host: &str) -> Duration {
+        self.buckets
+            .get(host)
+            .map(|bucket| bucket.interval)
+            .or_else(|| self.host_intervals.get(host).copied())
+            .unwrap_or(self.default_interval)
+    }
+}
+
+/// Wait asked for by a `Retry-After` header, given either in seconds or as
+/// an HTTP date.
+pub fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (at.with_timezone(&Utc) - now)
+            .to_std()
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use chrono::{TimeZone, Utc};
+    use tokio::time::Instant;
+
+    use crate::rate_limit::{parse_retry_after, RateLimiter};
+
+    #[test]
+    fn test_bucket_allows_burst_then_paces() {
+        let second = Duration::from_secs(1);
+        let mut limiter = RateLimiter::new(second, HashMap::new(), 2);
+        let now = Instant::now();
+        assert_eq!(limiter.reserve("a", now), now);
+        assert_eq!(limiter.reserve("a", now), now);
+        assert_eq!(limiter.reserve("a", now), now + second);
+        assert_eq!(limiter.reserve("a", now), now + second * 2);
+        // Other hosts have their own bucket.
+        assert_eq!(limiter.reserve("b", now), now);
+        // Asking doesn't take a token.
+        assert_eq!(limiter.ready_at("a", now), now + second * 3);
+        assert_eq!(limiter.ready_at("a", now), now + second * 3);
+    }
+
+    #[test]
+    fn test_throttled_host_slows_down_and_recovers() {
+        let second = Duration::from_secs(1);
+        let mut limiter = RateLimiter::new(second, HashMap::new(), 1);
+        let now = Instant::now();
+        assert_eq!(limiter.reserve("a", now), now);
+
+        limiter.throttled("a", Some(Duration::from_secs(30)), now);
+        assert_eq!(limiter.interval("a"), second * 2);
+        assert_eq!(limiter.ready_at("a", now), now + Duration::from_secs(30));
+        let start = limiter.reserve("a", now);
+        assert_eq!(start, now + Duration::from_secs(30));
+        assert_eq!(limiter.reserve("a", now), start + second * 2);
+
+        for _ in 0..100 {
+            limiter.succeeded("a", start);
+        }
+        assert!(limiter.interval("a") < second + Duration::from_millis(1));
+        assert!(limiter.interval("a") >= second);
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = Utc.with_ymd_and_hms(2015, 10, 21, 7, 27, 0).unwrap();
+        assert_eq!(
+            parse_retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT", now),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+use crate::livescores::Game;
+use crate::storage::natural_key;
+
+/// How a game parsed again from an archived page differs from the stored one.
+#[derive(Debug, PartialEq)]
+pub enum GameChange {
+    /// Not in the database yet.
+    Added(String),
+    /// `field` went from the stored value to the parsed one.
+    Changed {
+        key: String,
+        field: String,
+        stored: Value,
+        parsed: Value,
+    },
+}
+
+/// Compares the `parsed` games with the `stored` ones, matched by league, day
+/// and teams. Games only in the database aren't reported, a page shows few.
+pub fn diff_games(stored: &[Game], parsed: &[Game]) -> Vec<GameChange> {
+    let stored: BTreeMap<String, Value> = stored
+        .iter()
+        .map(|game| (natural_key(game), to_fields(game)))
+        .collect();
+    let mut changes = vec![];
+    for game in parsed {
+        let key = natural_key(game);
+        let Some(Value::Object(old)) = stored.get(&key) else {
+            changes.push(GameChange::Added(key));
+            continue;
+        };
+        let Value::Object(new) = to_fields(game) else {
+            continue;
+        };
+        for (field, parsed) in new {
+            let stored = old.get(&field).cloned().unwrap_or(Value::Null);
+            if stored != parsed {
+                changes.push(GameChange::Changed {
+                    key: key.clone(),
+                    field,
+                    stored,
+                    parsed,
+                });
+            }
+        }
+    }
+    changes
+}
+
+/// The game as JSON, without what the database doesn't keep.
+fn to_fields(game: &Game) -> Value {
+    let mut fields = serde_json::to_value(game).expect("serializable game");
+    if let Value::Object(map) = &mut fields {
+        // Teams are stored by name only.
+        map.insert("host".to_owned(), Value::from(game.host.name.as_str()));
+        map.insert("guest".to_owned(), Value::from(game.guest.name.as_str()));
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use serde_json::{json, Value};
+
+    use crate::livescores::{Game, MatchStatus, Team};
+    use crate::reparse::{diff_games, GameChange};
+
+    fn game(host: &str) -> Game {
+        Game {
+            status: MatchStatus::Finished(2, 0),
+            league: "Europa League 20/21".to_owned(),
+            stage: None,
+            start_date: NaiveDate::from_ymd_opt(2020, 12, 10).unwrap(),
+            host: Team {
+                name: host.to_owned(),
+                country: "".to_owned(),
+            },
+            guest: Team {
+                name: "AEK Athens".to_owned(),
+                country: "".to_owned(),
+            },
+            source_id: None,
+            detail_href: None,
+            kickoff: None,
+        }
+    }
+
+    #[test]
+    fn test_diff_reports_new_fields_and_games() {
+        let stored = [game("Leicester City")];
+        let mut backfilled = game("Leicester City");
+        backfilled.source_id = Some(316190);
+        backfilled.host.country = "England".to_owned();
+        let changes = diff_games(&stored, &[backfilled, game("Braga")]);
+        assert_eq!(
+            changes,
+            [
+                GameChange::Changed {
+                    key: "Europa League 20/21|2020-12-10|Leicester City|AEK Athens".to_owned(),
+                    field: "source_id".to_owned(),
+                    stored: Value::Null,
+                    parsed: json!(316190),
+                },
+                GameChange::Added("Europa League 20/21|2020-12-10|Braga|AEK Athens".to_owned()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+use rand::Rng;
+use serde::Serialize;
+use tokio::time::Instant;
+
+use crate::frontier::canonical_url;
+use crate::ScraperTask;
+
+pub struct RetryPolicy {
+    /// Number of fetches of a task, including the first one, before it is
+    /// moved to the dead-letter list.
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_secs(2),
+            max_delay: Duration::from_secs(300),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retrying after the `failures`-th failed attempt:
+    /// exponential in the number of failures, capped at `max_delay`, with the
+    /// upper half randomized so failed tasks don't come back in lockstep.
+    pub fn backoff(&self, failures: u32) -> Duration {
+        let exp = self
+            .base_delay
+            .saturating_mul(1 << failures.saturating_sub(1).min(16))
+            .min(self.max_delay);
+        let half = exp / 2;
+        half + half.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct DeadLetter {
+    #[serde(flatten)]
+    pub task: ScraperTask,
+    pub attempts: u32,
+    pub error: String,
+}
+
+/// Failed tasks waiting for their next attempt.
+#[derive(Default)]
+pub struct RetryQueue {
+    waiting: Vec<(Instant, ScraperTask)>,
+    failures: HashMap<String, u32>,
+    dead_letters: Vec<DeadLetter>,
+}
+
+impl RetryQueue {
+    /// Records a failed attempt of `task` and either schedules it again or,
+    /// once the policy's attempts are used up, moves it to the dead letters
+    /// and returns its entry.
+    pub fn schedule(
+        &mut self,
+        policy: &RetryPolicy,
+        task: ScraperTask,
+        error: String,
+    ) -> Option<&DeadLetter> {
+        let failures = self.failures.entry(canonical_url(&task)).or_insert(0);
+        *failures += 1;
+        if *failures >= policy.max_attempts {
+            self.dead_letters.push(DeadLetter {
+                task,
+                attempts: *failures,
+                error,
+            });
+            self.dead_letters.last()
+        } else {
+            let at = Instant::now() + policy.backoff(*failures);
+            self.waiting.push((at, task));
+            None
+        }
+    }
+
+    /// Moves `task` to the dead letters without retrying, for failures that
+    /// won't go away.
+    pub fn give_up(&mut self, task: ScraperTask, error: String) -> &DeadLetter {
+        let failures = self.failures.entry(canonical_url(&task)).or_insert(0);
+        *failures += 1;
+        self.dead_letters.push(DeadLetter {
+            task,
+            attempts: *failures,
+            error,
+        });
+        self.dead_letters.last().expect("just pushed")
+    }
+
+    /// Tasks waiting for their next attempt, with when it is due.
+    pub fn waiting(&self) -> impl Iterator<Item = (Instant, &ScraperTask)> {
+        self.waiting.iter().map(|(at, task)| (*at, task))
+    }
+
+    /// Takes a task that is due and that `ready` accepts.
+    pub fn pop_due<P>(&mut self, now: Instant, mut ready: P) -> Option<ScraperTask>
+    where
+        P: FnMut(&ScraperTask) -> bool,
+    {
+        let i = self
+            .waiting
+            .iter()
+            .position(|(at, task)| *at <= now && ready(task))?;
+        Some(self.waiting.swap_remove(i).1)
+    }
+
+    pub fn into_dead_letters(self) -> Vec<DeadLetter> {
+        self.dead_letters
+    }
+}
+
+/// Writes one JSON object per line.
+pub fn write_dead_letters(path: &Path, dead_letters: &[DeadLetter]) -> std::io::Result<()> {
+    let mut file = std::fs::File::create(path)?;
+    for dead_letter in dead_letters {
+        writeln!(file, "{}", serde_json::to_string(dead_letter)?)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use crate::livescores::GamesParser;
+    use crate::retry::{RetryPolicy, RetryQueue};
+    use crate::ScripingFunction;
+
+    #[test]
+    fn test_backoff_grows_and_is_capped() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_secs(2),
+            max_delay: Duration::from_secs(10),
+        };
+        let first = policy.backoff(1);
+        assert!(first >= Duration::from_secs(1) && first <= Duration::from_secs(2));
+        let third = policy.backoff(3);
+        assert!(third >= Duration::from_secs(4) && third <= Duration::from_secs(8));
+        let capped = policy.backoff(30);
+        assert!(capped >= Duration::from_secs(5) && capped <= Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_retry_queue_dead_letters_after_max_attempts() {
+        let policy = RetryPolicy {
+            max_attempts: 2,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+        };
+        let mut queue = RetryQueue::default();
+        let task = GamesParser::new_task("https://livescores.com", "/football/spain/");
+        assert!(queue
+            .schedule(&policy, task, "timeout".to_owned())
+            .is_none());
+        let task = queue
+            .pop_due(Instant::now(), |_| true)
+            .expect("task should be due");
+        assert!(queue.waiting().next().is_none());
+
+        assert!(queue
+            .schedule(&policy, task, "timeout".to_owned())
+            .is_some());
+        assert!(queue.pop_due(Instant::now(), |_| true).is_none());
+        let dead_letters = queue.into_dead_letters();
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].attempts, 2);
+        assert_eq!(dead_letters[0].task.href, "/football/spain/");
+    }
+}
@@ -0,0 +1,412 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use arrow::array::{
+    ArrayRef, Date32Array, Int32Array, StringArray, TimestampMillisecondArray, UInt32Array,
+    UInt64Array,
+};
+use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use chrono::{DateTime, NaiveDate, Utc};
+use parquet::arrow::ArrowWriter;
+use serde::Serialize;
+
+use crate::livescores::Game;
+use crate::{clock, ScraperTask, TaskData};
+
+pub type SinkResult = Result<(), Box<dyn Error>>;
+
+/// Destination for the data extracted during a crawl.
+pub trait Sink {
+    fn write(&mut self, task: &ScraperTask, data: &TaskData) -> SinkResult;
+    /// Flushes buffered data, called once at the end of the crawl.
+    fn finish(&mut self) -> SinkResult;
+}
+
+/// Opens the sink named `kind` ("jsonl", "csv" or "parquet") writing to
+/// `path`, a file for the first two and a directory for parquet.
+pub fn open_sink(kind: &str, path: &Path) -> Result<Box<dyn Sink>, Box<dyn Error>> {
+    match kind {
+        "jsonl" => Ok(Box::new(JsonlSink::create(path)?)),
+        "csv" => Ok(Box::new(CsvSink::create(path)?)),
+        "parquet" => Ok(Box::new(ParquetSink::new(path))),
+        _ => Err(format!("Unknown sink {}, expected jsonl, csv or parquet", kind).into()),
+    }
+}
+
+/// One game per row, shared by the tabular sinks.
+#[derive(Serialize)]
+struct GameRow {
+    source_id: Option<u64>,
+    league: String,
+    stage: Option<String>,
+    start_date: NaiveDate,
+    kickoff: Option<DateTime<Utc>>,
+    status: String,
+    minute: Option<u32>,
+    host: String,
+    guest: String,
+    host_score: Option<i32>,
+    guest_score: Option<i32>,
+    host_penalties: Option<i32>,
+    guest_penalties: Option<i32>,
+    detail_href: Option<String>,
+}
+
+impl GameRow {
+    fn new(game: &Game) -> GameRow {
+        let columns = game.status.columns();
+        GameRow {
+            source_id: game.source_id,
+            league: game.league.clone(),
+            stage: game.stage.clone(),
+            start_date: game.start_date,
+            kickoff: game.kickoff,
+            status: columns.status.to_owned(),
+            minute: columns.minute,
+            host: game.host.name.clone(),
+            guest: game.guest.name.clone(),
+            host_score: columns.score.map(|s| s.0),
+            guest_score: columns.score.map(|s| s.1),
+            host_penalties: columns.penalties.map(|s| s.0),
+            guest_penalties: columns.penalties.map(|s| s.1),
+            detail_href: game.detail_href.clone(),
+        }
+    }
+}
+
+/// Every payload as one JSON object per line, together with the page it
+/// came from.
+pub struct JsonlSink {
+    file: BufWriter<File>,
+}
+
+#[derive(Serialize)]
+struct JsonlRecord<'a> {
+    url: String,
+    parser: &'a str,
+    data: &'a TaskData,
+}
+
+impl JsonlSink {
+    pub fn create(path: &Path) -> std::io::Result<JsonlSink> {
+        Ok(JsonlSink {
+            file: BufWriter::new(File::create(path)?),
+        })
+    }
+}
+
+impl Sink for JsonlSink {
+    fn write(&mut self, task: &ScraperTask, data: &TaskData) -> SinkResult {
+        if let TaskData::Empty = data {
+            return Ok(());
+        }
+        let record = JsonlRecord {
+            url: format!("{}{}", task.url, task.href),
+            parser: &task.name,
+            data,
+        };
+        writeln!(self.file, "{}", serde_json::to_string(&record)?)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> SinkResult {
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+/// One row per game.
+pub struct CsvSink {
+    writer: csv::Writer<File>,
+}
+
+impl CsvSink {
+    pub fn create(path: &Path) -> csv::Result<CsvSink> {
+        Ok(CsvSink {
+            writer: csv::Writer::from_path(path)?,
+        })
+    }
+}
+
+impl Sink for CsvSink {
+    fn write(&mut self, _task: &ScraperTask, data: &TaskData) -> SinkResult {
+        if let TaskData::Games(page) = data {
+            for game in &page.games {
+                self.writer.serialize(GameRow::new(game))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> SinkResult {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Rows of a partition buffered before they are written out as a file.
+const ROWS_PER_FILE: usize = 100_000;
+
+/// Games written as `<dir>/date=<YYYY-MM-DD>/league=<league>/games-<run>-<n>.parquet`,
+/// a file whenever a partition has `rows_per_file` games and one for the rest
+/// at the end of the crawl. `<run>` is the start of the crawl and the process
+/// id, so later runs add files next to the earlier ones.
+pub struct ParquetSink {
+    dir: PathBuf,
+    run: String,
+    rows_per_file: usize,
+    partitions: BTreeMap<(NaiveDate, String), Partition>,
+}
+
+#[derive(Default)]
+struct Partition {
+    rows: Vec<GameRow>,
+    /// Files written so far.
+    files: usize,
+}
+
+impl ParquetSink {
+    pub fn new(dir: &Path) -> ParquetSink {
+        ParquetSink {
+            dir: dir.to_owned(),
+            run: format!(
+                "{}-{}",
+                clock::now().format("%Y%m%dT%H%M%S"),
+                std::process::id()
+            ),
+            rows_per_file: ROWS_PER_FILE,
+            partitions: BTreeMap::new(),
+        }
+    }
+
+    fn flush(&self, date: NaiveDate, league: &str, partition: &mut Partition) -> SinkResult {
+        if partition.rows.is_empty() {
+            return Ok(());
+        }
+        let dir = self
+            .dir
+            .join(format!("date={}", date))
+            .join(format!("league={}", partition_name(league)));
+        std::fs::create_dir_all(&dir)?;
+        partition.files += 1;
+        let name = format!("games-{}-{}.parquet", self.run, partition.files);
+        let file = File::create(dir.join(name))?;
+        let mut writer = ArrowWriter::try_new(file, Arc::new(Self::schema()), None)?;
+        writer.write(&Self::batch(&std::mem::take(&mut partition.rows))?)?;
+        writer.close()?;
+        Ok(())
+    }
+
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("source_id", DataType::UInt64, true),
+            Field::new("league", DataType::Utf8, false),
+            Field::new("stage", DataType::Utf8, true),
+            Field::new("start_date", DataType::Date32, false),
+            Field::new(
+                "kickoff",
+                DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+                true,
+            ),
+            Field::new("status", DataType::Utf8, false),
+            Field::new("minute", DataType::UInt32, true),
+            Field::new("host", DataType::Utf8, false),
+            Field::new("guest", DataType::Utf8, false),
+            Field::new("host_score", DataType::Int32, true),
+            Field::new("guest_score", DataType::Int32, true),
+            Field::new("host_penalties", DataType::Int32, true),
+            Field::new("guest_penalties", DataType::Int32, true),
+            Field::new("detail_href", DataType::Utf8, true),
+        ])
+    }
+
+    fn batch(rows: &[GameRow]) -> Result<RecordBatch, Box<dyn Error>> {
+        let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(UInt64Array::from_iter(rows.iter().map(|r| r.source_id))),
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|r| &r.league),
+            )),
+            Arc::new(StringArray::from_iter(
+                rows.iter().map(|r| r.stage.as_ref()),
+            )),
+            Arc::new(Date32Array::from_iter_values(
+                rows.iter()
+                    .map(|r| (r.start_date - epoch).num_days() as i32),
+            )),
+            Arc::new(
+                TimestampMillisecondArray::from_iter(
+                    rows.iter().map(|r| r.kickoff.map(|k| k.timestamp_millis())),
+                )
+                .with_timezone("UTC"),
+            ),
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|r| &r.status),
+            )),
+            Arc::new(UInt32Array::from_iter(rows.iter().map(|r| r.minute))),
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|r| &r.host))),
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|r| &r.guest))),
+            Arc::new(Int32Array::from_iter(rows.iter().map(|r| r.host_score))),
+            Arc::new(Int32Array::from_iter(rows.iter().map(|r| r.guest_score))),
+            Arc::new(Int32Array::from_iter(rows.iter().map(|r| r.host_penalties))),
+            Arc::new(Int32Array::from_iter(
+                rows.iter().map(|r| r.guest_penalties),
+            )),
+            Arc::new(StringArray::from_iter(
+                rows.iter().map(|r| r.detail_href.as_ref()),
+            )),
+        ];
+        Ok(RecordBatch::try_new(Arc::new(Self::schema()), columns)?)
+    }
+}
+
+impl Sink for ParquetSink {
+    fn write(&mut self, _task: &ScraperTask, data: &TaskData) -> SinkResult {
+        if let TaskData::Games(page) = data {
+            for game in &page.games {
+                let key = (game.start_date, game.league.clone());
+                let mut partition = self.partitions.remove(&key).unwrap_or_default();
+                partition.rows.push(GameRow::new(game));
+                if partition.rows.len() >= self.rows_per_file {
+                    self.flush(key.0, &key.1, &mut partition)?;
+                }
+                self.partitions.insert(key, partition);
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> SinkResult {
+        let mut partitions = std::mem::take(&mut self.partitions);
+        for ((date, league), partition) in &mut partitions {
+            self.flush(*date, league, partition)?;
+        }
+        self.partitions = partitions;
+        Ok(())
+    }
+}
+
+/// League name usable as a directory name: "Women's World Cup" becomes
+/// "women-s-world-cup".
+fn partition_name(league: &str) -> String {
+    let name: String = league
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect();
+    name.split('-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    use crate::clock;
+    use crate::livescores::{Game, GamesPage, GamesParser, MatchStatus, Team};
+    use crate::sink::{open_sink, partition_name, ParquetSink, Sink};
+    use crate::{ScripingFunction, TaskData};
+
+    fn games() -> TaskData {
+        let game = |host: &str, guest: &str, status| Game {
+            status,
+            league: "Europa League 20/21".to_owned(),
+            stage: None,
+            start_date: NaiveDate::from_ymd_opt(2020, 12, 10).unwrap(),
+            host: Team {
+                name: host.to_owned(),
+                country: "".to_owned(),
+            },
+            guest: Team {
+                name: guest.to_owned(),
+                country: "".to_owned(),
+            },
+            source_id: None,
+            detail_href: None,
+            kickoff: None,
+        };
+        TaskData::Games(GamesPage {
+            games: vec![
+                game("Leicester City", "AEK Athens", MatchStatus::Finished(2, 0)),
+                game("SC Braga", "Zorya", MatchStatus::Postponed),
+            ],
+        })
+    }
+
+    #[test]
+    fn test_csv_sink_writes_one_row_per_game() {
+        let path = std::env::temp_dir().join(format!("games_{}.csv", std::process::id()));
+        let task =
+            GamesParser::new_task("https://livescores.com", "/football/europa-league-20-21/");
+        let mut sink = open_sink("csv", &path).unwrap();
+        sink.write(&task, &games()).unwrap();
+        sink.finish().unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("source_id,league,stage,start_date,kickoff,status"));
+        assert!(lines[1].contains("Leicester City,AEK Athens,2,0"));
+        assert!(lines[2].contains(",postponed,"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_parquet_sink_partitions_by_date_and_league() {
+        let dir = std::env::temp_dir().join(format!("games_{}", std::process::id()));
+        let task =
+            GamesParser::new_task("https://livescores.com", "/football/europa-league-20-21/");
+        let rows = |mut sink: ParquetSink| -> Vec<i64> {
+            sink.write(&task, &games()).unwrap();
+            sink.finish().unwrap();
+            let partition = dir.join("date=2020-12-10/league=europa-league-20-21");
+            let mut files: Vec<_> = std::fs::read_dir(&partition)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .collect();
+            files.sort();
+            files
+                .iter()
+                .map(|file| {
+                    let reader =
+                        SerializedFileReader::new(std::fs::File::open(file).unwrap()).unwrap();
+                    reader.metadata().file_metadata().num_rows()
+                })
+                .collect()
+        };
+        let first = clock::with_time(
+            Utc.with_ymd_and_hms(2020, 12, 10, 20, 0, 0).unwrap(),
+            || ParquetSink::new(&dir),
+        );
+        assert_eq!(first.run, format!("20201210T200000-{}", std::process::id()));
+        assert_eq!(rows(first), [2]);
+        // A partition that fills up is written out, and a later run adds its
+        // files next to the earlier ones.
+        let second = clock::with_time(
+            Utc.with_ymd_and_hms(2020, 12, 11, 20, 0, 0).unwrap(),
+            || ParquetSink {
+                rows_per_file: 1,
+                ..ParquetSink::new(&dir)
+            },
+        );
+        assert_eq!(rows(second), [2, 1, 1]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_partition_name() {
+        assert_eq!(
+            partition_name("Women's World Cup Qualification"),
+            "women-s-world-cup-qualification"
+        );
+        assert!(open_sink("xml", std::path::Path::new("out.xml")).is_err());
+    }
+}
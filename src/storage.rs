@@ -0,0 +1,434 @@
+use std::path::Path;
+
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row, Transaction};
+
+use crate::livescores::{Game, MatchStatus, StatusColumns, Team};
+
+/// SQLite database with the scraped leagues, stages, teams and games.
+pub struct Storage {
+    conn: Connection,
+}
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS leagues (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE
+);
+CREATE TABLE IF NOT EXISTS stages (
+    id INTEGER PRIMARY KEY,
+    league_id INTEGER NOT NULL REFERENCES leagues(id),
+    name TEXT NOT NULL,
+    UNIQUE (league_id, name)
+);
+CREATE TABLE IF NOT EXISTS teams (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE
+);
+CREATE TABLE IF NOT EXISTS games (
+    id INTEGER PRIMARY KEY,
+    match_key TEXT NOT NULL UNIQUE,
+    source_id INTEGER,
+    detail_href TEXT,
+    league_id INTEGER NOT NULL REFERENCES leagues(id),
+    stage_id INTEGER REFERENCES stages(id),
+    start_date TEXT NOT NULL,
+    start_time TEXT,
+    kickoff TEXT,
+    host_id INTEGER NOT NULL REFERENCES teams(id),
+    guest_id INTEGER NOT NULL REFERENCES teams(id),
+    status TEXT NOT NULL,
+    minute INTEGER,
+    host_score INTEGER,
+    guest_score INTEGER,
+    host_penalties INTEGER,
+    guest_penalties INTEGER,
+    updated_at TEXT NOT NULL
+);
+";
+
+/// Version of `SCHEMA`, kept in the database's `user_version`. Databases
+/// without one were created with the first schema.
+const SCHEMA_VERSION: i64 = 2;
+
+/// Columns added to `games` after the first schema.
+const ADDED_GAME_COLUMNS: [(&str, &str); 6] = [
+    ("source_id", "INTEGER"),
+    ("detail_href", "TEXT"),
+    ("kickoff", "TEXT"),
+    ("minute", "INTEGER"),
+    ("host_penalties", "INTEGER"),
+    ("guest_penalties", "INTEGER"),
+];
+
+impl Storage {
+    pub fn open(path: &Path) -> rusqlite::Result<Storage> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> rusqlite::Result<Storage> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(mut conn: Connection) -> rusqlite::Result<Storage> {
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version < SCHEMA_VERSION {
+            migrate(&mut conn)?;
+        }
+        Ok(Storage { conn })
+    }
+
+    /// Inserts the games or, for games already stored under the same match
+    /// key, updates their status and score.
+    pub fn upsert_games(&mut self, games: &[Game]) -> rusqlite::Result<()> {
+        let tx = self.conn.transaction()?;
+        for game in games {
+            upsert_game(&tx, game)?;
+        }
+        tx.commit()
+    }
+
+    /// All stored games, ordered by day and league.
+    pub fn games(&self) -> rusqlite::Result<Vec<Game>> {
+        let mut statement = self.conn.prepare(
+            "SELECT leagues.name, stages.name, games.start_date, games.start_time,
+                    games.kickoff, host.name, guest.name, games.status, games.minute,
+                    games.host_score, games.guest_score, games.host_penalties,
+                    games.guest_penalties, games.source_id, games.detail_href
+             FROM games
+             JOIN leagues ON leagues.id = games.league_id
+             LEFT JOIN stages ON stages.id = games.stage_id
+             JOIN teams AS host ON host.id = games.host_id
+             JOIN teams AS guest ON guest.id = games.guest_id
+             ORDER BY games.start_date, leagues.name, games.id",
+        )?;
+        let games = statement.query_map([], read_game)?;
+        games.collect()
+    }
+}
+
+/// Creates the tables missing from the database and adds the columns that
+/// tables created by an older schema lack.
+fn migrate(conn: &mut Connection) -> rusqlite::Result<()> {
+    let tx = conn.transaction()?;
+    tx.execute_batch(SCHEMA)?;
+    let columns = tx
+        .prepare("SELECT name FROM pragma_table_info('games')")?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    for (column, kind) in ADDED_GAME_COLUMNS {
+        if !columns.iter().any(|name| name == column) {
+            tx.execute_batch(&format!("ALTER TABLE games ADD COLUMN {} {}", column, kind))?;
+        }
+    }
+    tx.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+    tx.commit()
+}
+
+fn read_game(row: &Row) -> rusqlite::Result<Game> {
+    let start_date: String = row.get(2)?;
+    let start_time: Option<String> = row.get(3)?;
+    let kickoff: Option<String> = row.get(4)?;
+    let status: String = row.get(7)?;
+    let pair = |a: Option<i32>, b: Option<i32>| a.zip(b);
+    let columns = StatusColumns {
+        status: &status,
+        start_time: start_time.and_then(|t| NaiveTime::parse_from_str(&t, "%H:%M").ok()),
+        minute: row.get(8)?,
+        score: pair(row.get(9)?, row.get(10)?),
+        penalties: pair(row.get(11)?, row.get(12)?),
+    };
+    let source_id: Option<i64> = row.get(13)?;
+    Ok(Game {
+        status: MatchStatus::from_columns(&columns),
+        league: row.get(0)?,
+        stage: row.get(1)?,
+        start_date: NaiveDate::parse_from_str(&start_date, "%Y-%m-%d").map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(2, rusqlite::types::Type::Text, e.into())
+        })?,
+        host: Team {
+            name: row.get(5)?,
+            country: "".to_owned(),
+        },
+        guest: Team {
+            name: row.get(6)?,
+            country: "".to_owned(),
+        },
+        source_id: source_id.map(|id| id as u64),
+        detail_href: row.get(14)?,
+        kickoff: kickoff
+            .and_then(|k| DateTime::parse_from_rfc3339(&k).ok())
+            .map(|k| k.with_timezone(&Utc)),
+    })
+}
+
+fn upsert_game(tx: &Transaction, game: &Game) -> rusqlite::Result<()> {
+    let league_id = upsert_named(tx, "leagues", &game.league)?;
+    let stage_id = match &game.stage {
+        Some(stage) => Some(upsert_stage(tx, league_id, stage)?),
+        None => None,
+    };
+    let host_id = upsert_named(tx, "teams", &game.host.name)?;
+    let guest_id = upsert_named(tx, "teams", &game.guest.name)?;
+    let columns = game.status.columns();
+    if game.source_id.is_some() {
+        // Take over the row of the same game stored before its match id was
+        // known, or drop it when the id already has a row of its own.
+        let taken_over = tx.execute(
+            "UPDATE OR IGNORE games SET match_key = ?1, source_id = ?2 WHERE match_key = ?3",
+            params![
+                match_key(game),
+                game.source_id.map(|id| id as i64),
+                natural_key(game)
+            ],
+        )?;
+        if taken_over == 0 {
+            tx.execute(
+                "DELETE FROM games WHERE match_key = ?1",
+                params![natural_key(game)],
+            )?;
+        }
+    }
+    tx.execute(
+        "INSERT INTO games (match_key, league_id, stage_id, start_date, start_time,
+                            host_id, guest_id, status, host_score, guest_score, updated_at,
+                            source_id, detail_href, minute, host_penalties, guest_penalties, kickoff)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)
+         ON CONFLICT (match_key) DO UPDATE SET
+            start_date = excluded.start_date,
+            detail_href = COALESCE(excluded.detail_href, games.detail_href),
+            stage_id = excluded.stage_id,
+            start_time = COALESCE(excluded.start_time, games.start_time),
+            kickoff = COALESCE(excluded.kickoff, games.kickoff),
+            status = excluded.status,
+            host_score = excluded.host_score,
+            guest_score = excluded.guest_score,
+            minute = excluded.minute,
+            host_penalties = excluded.host_penalties,
+            guest_penalties = excluded.guest_penalties,
+            updated_at = excluded.updated_at",
+        params![
+            match_key(game),
+            league_id,
+            stage_id,
+            game.start_date.to_string(),
+            columns.start_time.map(|time| time.format("%H:%M").to_string()),
+            host_id,
+            guest_id,
+            columns.status,
+            columns.score.map(|s| s.0),
+            columns.score.map(|s| s.1),
+            Utc::now().to_rfc3339(),
+            game.source_id.map(|id| id as i64),
+            game.detail_href,
+            columns.minute,
+            columns.penalties.map(|s| s.0),
+            columns.penalties.map(|s| s.1),
+            game.kickoff.map(|kickoff| kickoff.to_rfc3339()),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Identifies a game across crawls: the site's match id when known,
+/// otherwise league, day and both teams.
+pub fn match_key(game: &Game) -> String {
+    match game.source_id {
+        Some(id) => format!("livescores:{}", id),
+        None => natural_key(game),
+    }
+}
+
+/// League, day and both teams of a game, known however it was scraped.
+pub fn natural_key(game: &Game) -> String {
+    format!(
+        "{}|{}|{}|{}",
+        game.league, game.start_date, game.host.name, game.guest.name
+    )
+}
+
+fn upsert_named(tx: &Transaction, table: &str, name: &str) -> rusqlite::Result<i64> {
+    tx.execute(
+        &format!("INSERT OR IGNORE INTO {} (name) VALUES (?1)", table),
+        params![name],
+    )?;
+    tx.query_row(
+        &format!("SELECT id FROM {} WHERE name = ?1", table),
+        params![name],
+        |row| row.get(0),
+    )
+}
+
+fn upsert_stage(tx: &Transaction, league_id: i64, name: &str) -> rusqlite::Result<i64> {
+    let existing = tx
+        .query_row(
+            "SELECT id FROM stages WHERE league_id = ?1 AND name = ?2",
+            params![league_id, name],
+            |row| row.get(0),
+        )
+        .optional()?;
+    match existing {
+        Some(id) => Ok(id),
+        None => {
+            tx.execute(
+                "INSERT INTO stages (league_id, name) VALUES (?1, ?2)",
+                params![league_id, name],
+            )?;
+            Ok(tx.last_insert_rowid())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveTime};
+    use rusqlite::Connection;
+
+    use crate::livescores::{Game, MatchStatus, Team};
+    use crate::storage::{Storage, SCHEMA_VERSION};
+
+    fn game(status: MatchStatus) -> Game {
+        Game {
+            status,
+            league: "Europa League".to_owned(),
+            stage: Some("Group G".to_owned()),
+            start_date: NaiveDate::from_ymd_opt(2020, 12, 10).unwrap(),
+            host: Team {
+                name: "Leicester City".to_owned(),
+                country: "".to_owned(),
+            },
+            guest: Team {
+                name: "AEK Athens".to_owned(),
+                country: "".to_owned(),
+            },
+            source_id: Some(316190),
+            detail_href: None,
+            kickoff: None,
+        }
+    }
+
+    #[test]
+    fn test_upsert_updates_score_of_known_game() {
+        let mut storage = Storage::open_in_memory().unwrap();
+        let kickoff = NaiveTime::from_hms_opt(20, 0, 0).unwrap();
+        storage
+            .upsert_games(&[game(MatchStatus::Scheduled(kickoff))])
+            .unwrap();
+        storage
+            .upsert_games(&[game(MatchStatus::Finished(2, 0))])
+            .unwrap();
+
+        let (count, status, score, start_time): (i64, String, i32, String) = storage
+            .conn
+            .query_row(
+                "SELECT COUNT(*), status, host_score, start_time FROM games",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(status, "finished");
+        assert_eq!(score, 2);
+        assert_eq!(start_time, "20:00");
+        let teams: i64 = storage
+            .conn
+            .query_row("SELECT COUNT(*) FROM teams", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(teams, 2);
+    }
+
+    #[test]
+    fn test_match_id_takes_over_game_stored_without_it() {
+        let mut storage = Storage::open_in_memory().unwrap();
+        let mut without_id = game(MatchStatus::Finished(2, 0));
+        without_id.source_id = None;
+        storage.upsert_games(&[without_id.clone()]).unwrap();
+        storage
+            .upsert_games(&[game(MatchStatus::Finished(2, 0))])
+            .unwrap();
+
+        let games = storage.games().unwrap();
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].source_id, Some(316190));
+
+        // Seen without its id once more, then with it again.
+        storage.upsert_games(&[without_id]).unwrap();
+        assert_eq!(storage.games().unwrap().len(), 2);
+        storage
+            .upsert_games(&[game(MatchStatus::Finished(3, 0))])
+            .unwrap();
+        let games = storage.games().unwrap();
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].status, MatchStatus::Finished(3, 0));
+    }
+
+    #[test]
+    fn test_games_reads_back_stored_games() {
+        let mut storage = Storage::open_in_memory().unwrap();
+        let shootout = MatchStatus::FinishedOnPenalties(1, 1, Some((4, 3)));
+        storage.upsert_games(&[game(shootout)]).unwrap();
+
+        let games = storage.games().unwrap();
+        assert_eq!(games.len(), 1);
+        assert_eq!(
+            games[0].status,
+            MatchStatus::FinishedOnPenalties(1, 1, Some((4, 3)))
+        );
+        assert_eq!(games[0].stage.as_deref(), Some("Group G"));
+        assert_eq!(games[0].host.name, "Leicester City");
+        assert_eq!(games[0].source_id, Some(316190));
+    }
+
+    #[test]
+    fn test_open_migrates_first_schema() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE leagues (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE);
+             CREATE TABLE stages (
+                 id INTEGER PRIMARY KEY,
+                 league_id INTEGER NOT NULL REFERENCES leagues(id),
+                 name TEXT NOT NULL,
+                 UNIQUE (league_id, name)
+             );
+             CREATE TABLE teams (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE);
+             CREATE TABLE games (
+                 id INTEGER PRIMARY KEY,
+                 match_key TEXT NOT NULL UNIQUE,
+                 league_id INTEGER NOT NULL REFERENCES leagues(id),
+                 stage_id INTEGER REFERENCES stages(id),
+                 start_date TEXT NOT NULL,
+                 start_time TEXT,
+                 host_id INTEGER NOT NULL REFERENCES teams(id),
+                 guest_id INTEGER NOT NULL REFERENCES teams(id),
+                 status TEXT NOT NULL,
+                 host_score INTEGER,
+                 guest_score INTEGER,
+                 updated_at TEXT NOT NULL
+             );
+             INSERT INTO leagues (id, name) VALUES (1, 'Premier League');
+             INSERT INTO teams (id, name) VALUES (1, 'Arsenal'), (2, 'Chelsea');
+             INSERT INTO games (match_key, league_id, start_date, host_id, guest_id,
+                                status, host_score, guest_score, updated_at)
+             VALUES ('Premier League|2020-12-26|Arsenal|Chelsea', 1, '2020-12-26', 1, 2,
+                     'finished', 3, 1, '2020-12-26T20:00:00+00:00');",
+        )
+        .unwrap();
+
+        let mut storage = Storage::with_connection(conn).unwrap();
+        storage
+            .upsert_games(&[game(MatchStatus::Finished(2, 0))])
+            .unwrap();
+        let games = storage.games().unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].status, MatchStatus::Finished(2, 0));
+        assert_eq!(games[0].source_id, Some(316190));
+        assert_eq!(games[1].host.name, "Arsenal");
+        assert_eq!(games[1].status, MatchStatus::Finished(3, 1));
+        let version: i64 = storage
+            .conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, SCHEMA_VERSION);
+    }
+}